@@ -1,60 +1,245 @@
-use anyhow::Result;
-use testing_framework::{config::Config, sampler::Sampler};
-use testing_framework::config::{find_suitable_connectors, Key, PaymentRecorderData, Status, StraightThroughRouting};
-use testing_framework::evaluator::Evaluator;
-use testing_framework::recorder::Recorder;
-use testing_framework::config::Metrics;
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use testing_framework::coverage::{analyze_coverage, print_coverage};
+use testing_framework::config::{connector_eligibility, unroutable_reason, Config, Eligibility, Metrics, RoutingConfig, Sample};
+use testing_framework::recorder::{load_metrics, print_classification_metrics, print_failure_metrics, print_latency_metrics, print_comparison, print_metrics, print_regret_metrics, print_timeline_metrics, CsvRecorder};
+use testing_framework::rng::random_seed;
+use testing_framework::simulation::Simulation;
+use testing_framework::stats::DEFAULT_MIN_TRANSACTIONS;
 
+/// Simulate payments against PSP and merchant configs to benchmark routing algorithms.
+#[derive(Parser)]
+#[command(name = "testing-framework", version)]
+struct Cli {
+    /// Config file; defaults to $CONFIG_FILE, ./input.json or ./config.json
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+    /// Print per-transaction details
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
 
+#[derive(Subcommand)]
+enum Command {
+    /// Run a simulation and print the metrics
+    Run(RunArgs),
+    /// Check the config file without running anything
+    Validate,
+    /// Print the metrics of previously recorded transactions
+    Report(ReportArgs),
+    /// Run several routers on the same samples and compare them side by side
+    Compare(CompareArgs),
+    /// Show which connectors can take a hand-written sample, and why the others can't
+    Explain(ExplainArgs),
+}
 
-fn generate_user_sample(config: &Config) -> Result<(String, Vec<Key>)> {
-    let output = config.user.generate_sample()?;
-    let connectors = find_suitable_connectors(&output, &config.merchant);
-    let output = serde_json::to_string_pretty(&output)?;
-    Ok((output, connectors))
+#[derive(Args)]
+struct RunArgs {
+    /// Number of transactions to simulate
+    #[arg(short = 'n', long, default_value_t = 1500)]
+    iterations: u64,
+    /// Routing algorithm to use instead of the config's `routing` block, with default options
+    #[arg(short, long)]
+    router: Option<String>,
+    /// Seed for the simulation, overriding the config's `seed`
+    #[arg(short, long)]
+    seed: Option<u64>,
+    /// CSV file the transactions are written to
+    #[arg(short, long, default_value = "records.csv")]
+    output: PathBuf,
+    /// Add to the output files instead of replacing them
+    #[arg(long)]
+    append: bool,
+    /// Classify every transaction against the best connector, as with `"classification": true`
+    #[arg(long)]
+    classification: bool,
+    /// CSV file the classifications are written to
+    #[arg(long, default_value = "classification.csv")]
+    classification_output: PathBuf,
+    /// Also print the transactions of every BUCKET seconds of simulated time
+    #[arg(long, value_name = "BUCKET")]
+    bucket_secs: Option<u64>,
+    /// Flag success rates over fewer transactions and leave them out of significance tests
+    #[arg(long, default_value_t = DEFAULT_MIN_TRANSACTIONS)]
+    min_transactions: usize,
 }
 
-fn call_script(metrics: &mut Metrics) -> Result<()> {
-    let config = Config::load()?;
-    let (user_sample, connectors) = generate_user_sample(&config)?;
-    println!("User sample: {}", user_sample);
-    if connectors.is_empty() {
-        println!("No connectors available for this user in merchant config.");
-        return Ok(());
+#[derive(Args)]
+struct ReportArgs {
+    /// CSV file written by `run --output`
+    #[arg(long, default_value = "records.csv")]
+    records: PathBuf,
+    /// CSV file written by `run --classification-output`
+    #[arg(long)]
+    classification: Option<PathBuf>,
+    /// Also print the transactions of every BUCKET seconds of simulated time
+    #[arg(long, value_name = "BUCKET")]
+    bucket_secs: Option<u64>,
+    /// Flag success rates over fewer transactions and leave them out of significance tests
+    #[arg(long, default_value_t = DEFAULT_MIN_TRANSACTIONS)]
+    min_transactions: usize,
+}
+
+#[derive(Args)]
+struct CompareArgs {
+    /// Comma-separated routing algorithms, with default options; `config` is the config's `routing` block
+    #[arg(short, long, value_delimiter = ',', required = true)]
+    routers: Vec<String>,
+    /// Number of transactions to simulate per router
+    #[arg(short = 'n', long, default_value_t = 1500)]
+    iterations: u64,
+    /// Seed shared by all routers, overriding the config's `seed`
+    #[arg(short, long)]
+    seed: Option<u64>,
+}
+
+#[derive(Args)]
+struct ExplainArgs {
+    /// Sample as JSON, e.g. '{"amount": 1650, "currency": "USD", "payment_method": "card", "payment_method_type": "credit", "attributes": {"auth_type": "3ds"}}'
+    sample: String,
+}
+
+fn load_config(path: Option<&PathBuf>) -> Result<Config> {
+    match path {
+        Some(path) => Config::load_from(path),
+        None => Config::load(),
+    }
+}
+
+fn run(cli: &Cli, args: &RunArgs) -> Result<()> {
+    let config = load_config(cli.config.as_ref())?;
+    let routing = match &args.router {
+        Some(name) => RoutingConfig::from_name(name)?,
+        None => config.routing.clone(),
+    };
+    let seed = args.seed.or(config.seed).unwrap_or_else(random_seed);
+    print_coverage(&analyze_coverage(&config.user, &config.merchant)?);
+    let classification = config.classification || args.classification;
+    let csv_recorder = CsvRecorder::new(
+        &args.output,
+        classification.then_some(&args.classification_output),
+        seed,
+        args.append,
+    )?;
+    let mut simulation = Simulation::new(config, &routing, seed)?
+        .with_recorder(Box::new(csv_recorder))
+        .with_classification(args.classification)
+        .with_time_buckets(args.bucket_secs.map(Duration::from_secs))
+        .with_verbose(cli.verbose > 0);
+    simulation.run(args.iterations)?;
+
+    // Use recorder to print metrics
+    println!("Seed: {}", simulation.seed());
+    println!("Simulated time: {:.1}s", simulation.elapsed().as_secs_f64());
+    println!("Peak in-flight payments: {}", simulation.peak_in_flight());
+    for line in simulation.router().summary() {
+        println!("{}", line);
     }
-    println!("Available connectors for this user:");
-    for connector in &connectors {
-        println!("{}", connector.0);
+    print_metrics(simulation.metrics(), args.min_transactions);
+    print_latency_metrics(simulation.metrics());
+    print_failure_metrics(simulation.metrics());
+    print_classification_metrics(simulation.metrics());
+    print_regret_metrics(simulation.metrics());
+    print_timeline_metrics(simulation.metrics(), args.min_transactions);
+    Ok(())
+}
+
+/// Every router gets its own simulation from the same seed. The first one draws the
+/// payments and the others replay its arrival times and samples, so every router sees
+/// the same payments at the same times, and the PSPs draw their outcomes from
+/// identically seeded streams.
+fn compare(cli: &Cli, args: &CompareArgs) -> Result<()> {
+    let seed = match args.seed {
+        Some(seed) => seed,
+        None => load_config(cli.config.as_ref())?.seed.unwrap_or_else(random_seed),
+    };
+    let mut simulations: Vec<(String, Simulation)> = Vec::new();
+    for name in &args.routers {
+        let config = load_config(cli.config.as_ref())?;
+        let routing = match name.as_str() {
+            "config" => config.routing.clone(),
+            name => RoutingConfig::from_name(name)?,
+        };
+        let simulation = Simulation::new(config, &routing, seed)?.with_verbose(cli.verbose > 0);
+        let mut simulation = match simulations.first().and_then(|(_, first)| first.arrival_log()) {
+            Some(arrivals) => simulation.with_replay(arrivals),
+            None => simulation.with_arrival_log(),
+        };
+        simulation.run(args.iterations)?;
+        simulations.push((name.clone(), simulation));
     }
 
-    let routing = StraightThroughRouting {connectors};
-    let connector = routing.get_connector(); // Get the connector name as a string
+    println!("Seed: {}", seed);
+    for (_, simulation) in &simulations {
+        for line in simulation.router().summary() {
+            println!("{}", line);
+        }
+    }
+    let runs: Vec<(String, &Metrics)> = simulations
+        .iter()
+        .map(|(name, simulation)| (name.clone(), simulation.metrics()))
+        .collect();
+    print_comparison(&runs);
+    Ok(())
+}
 
-    println!("Using connector: {:?}", connector.0);
-    match config.psp.call_evaluator(&connector, &user_sample)? {
-        Status::Success => {
-            println!("Transaction succeeded.");
-            // Call recorder
-            let record_data = PaymentRecorderData::set_values(connector.clone(), Status::Success, Key(user_sample.clone()));
-            record_data.record_transaction(metrics)?;
-        },
-        Status::Failure => {
-            println!("Transaction failed.");
-            // Call recorder
-            let record_data = PaymentRecorderData::set_values(connector.clone(), Status::Failure, Key(user_sample.clone()));
-            record_data.record_transaction(metrics)?;
-        },
+fn validate(cli: &Cli) -> Result<()> {
+    let path = match &cli.config {
+        Some(path) => path.clone(),
+        None => Config::default_path()?,
+    };
+    let problems = Config::load_unvalidated(&path)?.validate();
+    if problems.is_empty() {
+        println!("Config is valid.");
+        return Ok(());
     }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    anyhow::bail!("{} problem(s) found in {:?}", problems.len(), path)
+}
 
+fn explain(cli: &Cli, args: &ExplainArgs) -> Result<()> {
+    let config = load_config(cli.config.as_ref())?;
+    let sample: Sample = serde_json::from_str(&args.sample).context("Failed to parse sample")?;
+    let eligibility = connector_eligibility(&sample, &config.merchant);
+    for (connector, eligibility) in &eligibility {
+        println!("{}: {}", connector.0, eligibility);
+    }
+    if !eligibility.iter().any(|(_, eligibility)| *eligibility == Eligibility::Eligible) {
+        println!("Unroutable: {}", unroutable_reason(&eligibility));
+    }
     Ok(())
 }
 
 fn main() -> Result<()> {
-    let mut metrics = Metrics::new();
-    for _ in 0..1 {
-        call_script(&mut metrics)?;
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    match &cli.command {
+        Command::Run(args) => run(&cli, args),
+        Command::Compare(args) => compare(&cli, args),
+        Command::Validate => validate(&cli),
+        Command::Explain(args) => explain(&cli, args),
+        Command::Report(args) => {
+            let bucket = args.bucket_secs.map(Duration::from_secs);
+            let metrics = load_metrics(&args.records, args.classification.as_ref(), bucket)?;
+            let seeds: Vec<String> = metrics.seeds.iter().map(u64::to_string).collect();
+            match seeds.len() {
+                0 => {}
+                1 => println!("Seed: {}", seeds[0]),
+                _ => println!("Seeds: {}", seeds.join(", ")),
+            }
+            print_metrics(&metrics, args.min_transactions);
+            print_latency_metrics(&metrics);
+            print_failure_metrics(&metrics);
+            print_classification_metrics(&metrics);
+            print_regret_metrics(&metrics);
+            print_timeline_metrics(&metrics, args.min_transactions);
+            Ok(())
+        }
     }
-    // Use recorder to print metrics
-    testing_framework::recorder::print_metrics(&metrics);
-    Ok(())
-}
\ No newline at end of file
+}
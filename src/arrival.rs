@@ -0,0 +1,90 @@
+use std::time::Duration;
+use anyhow::{ensure, Result};
+use rand::Rng;
+use rand_distr::{Distribution, Exp};
+use crate::config::ArrivalProcess;
+use crate::rng::SimRng;
+
+const DAY_SECS: f64 = 24.0 * 3600.0;
+
+/// Draws the simulated arrival time of every payment from the configured process.
+/// Time only exists on this virtual clock; nothing ever sleeps.
+pub struct Arrivals {
+    process: ArrivalProcess,
+    rng: SimRng,
+    /// Arrival time of the previous payment, in seconds.
+    last: f64,
+}
+
+impl Arrivals {
+    pub fn new(process: ArrivalProcess, rng: SimRng) -> Result<Self> {
+        let rates = match process {
+            ArrivalProcess::Sequential => vec![],
+            ArrivalProcess::Constant { tps } | ArrivalProcess::Poisson { tps } => vec![tps],
+            ArrivalProcess::Diurnal { tps, amplitude, .. } => {
+                ensure!((0.0..=1.0).contains(&amplitude), "Diurnal amplitude must be between 0 and 1");
+                vec![tps]
+            }
+            ArrivalProcess::Bursts { tps, burst_tps, every_ms, .. } => {
+                ensure!(every_ms > 0, "Bursts need a positive every_ms");
+                vec![tps, burst_tps]
+            }
+        };
+        ensure!(
+            rates.iter().all(|tps| *tps > 0.0 && tps.is_finite()),
+            "Arrival rates (tps) must be positive"
+        );
+        Ok(Arrivals { process, rng, last: 0.0 })
+    }
+
+    /// Whether the next payment arrives only once the previous one has completed.
+    pub fn is_sequential(&self) -> bool {
+        matches!(self.process, ArrivalProcess::Sequential)
+    }
+
+    /// Arrival time of the next payment. `idle_since` is when the previous payment
+    /// completed, which is when the next one arrives with `sequential` arrivals.
+    pub fn next_arrival(&mut self, idle_since: Duration) -> Duration {
+        self.last = match self.process {
+            ArrivalProcess::Sequential => idle_since.as_secs_f64(),
+            ArrivalProcess::Constant { tps } => self.last + 1.0 / tps,
+            ArrivalProcess::Poisson { tps } => self.last + self.exponential(tps),
+            ArrivalProcess::Diurnal { tps, amplitude, .. } => self.next_thinned(tps * (1.0 + amplitude)),
+            ArrivalProcess::Bursts { tps, burst_tps, .. } => self.next_thinned(tps.max(burst_tps)),
+        };
+        Duration::from_secs_f64(self.last)
+    }
+
+    /// Next arrival of a process whose rate varies over time and never exceeds `peak`:
+    /// candidates arrive at the peak rate and are kept with probability rate / peak.
+    fn next_thinned(&mut self, peak: f64) -> f64 {
+        let mut time = self.last;
+        loop {
+            time += self.exponential(peak);
+            if self.rng.gen_bool((self.rate(time) / peak).clamp(0.0, 1.0)) {
+                return time;
+            }
+        }
+    }
+
+    /// Arrival rate in payments per second at `time` seconds into the run.
+    fn rate(&self, time: f64) -> f64 {
+        match self.process {
+            ArrivalProcess::Sequential => 0.0,
+            ArrivalProcess::Constant { tps } | ArrivalProcess::Poisson { tps } => tps,
+            ArrivalProcess::Diurnal { tps, amplitude, peak_hour } => {
+                let hours_from_peak = (time % DAY_SECS) / 3600.0 - peak_hour;
+                tps * (1.0 + amplitude * (hours_from_peak / 24.0 * std::f64::consts::TAU).cos())
+            }
+            ArrivalProcess::Bursts { tps, burst_tps, every_ms, duration_ms } => {
+                let offset = (time * 1000.0) as u64 % every_ms;
+                if offset < duration_ms { burst_tps } else { tps }
+            }
+        }
+    }
+
+    fn exponential(&mut self, tps: f64) -> f64 {
+        // The rates were checked in `new`.
+        Exp::new(tps).map_or(0.0, |exp| exp.sample(&mut self.rng))
+    }
+}
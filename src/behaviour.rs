@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::config::{sorted, Key, Sample};
+
+/// The `supported_behaviours` of a connector's payment method: predicates on the
+/// sample, keyed by name, that must all hold for the connector to be eligible, e.g.
+/// `{"amount_less_than": 1500, "currency_in": ["USD", "EUR"]}`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "Map<String, Value>", into = "Map<String, Value>")]
+pub struct Behaviours(pub Vec<Predicate>);
+
+/// A single rule of `supported_behaviours`. Amounts are in minor units of whatever
+/// currency the sample is in, and `amount_between` includes both bounds.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Predicate {
+    AmountLessThan(u64),
+    AmountGreaterThan(u64),
+    AmountBetween { min: u64, max: u64 },
+    CurrencyIn(Vec<String>),
+    CurrencyNotIn(Vec<String>),
+    /// The sample's `country` attribute is one of these.
+    CountryIn(Vec<String>),
+    /// The sample's `card_network` attribute is one of these.
+    CardNetworkIn(Vec<String>),
+    /// The sample's `auth_type` attribute equals this.
+    AuthType(String),
+    /// Every listed sample attribute equals its value.
+    AttributeEquals(HashMap<Key, Value>),
+    /// Every listed sample attribute is one of its values.
+    AttributeIn(HashMap<Key, Vec<Value>>),
+    All(Vec<Behaviours>),
+    Any(Vec<Behaviours>),
+    Not(Box<Behaviours>),
+}
+
+impl Behaviours {
+    /// Why the sample breaks the rules, or `None` when every predicate holds.
+    pub fn violation(&self, sample: &Sample) -> Option<String> {
+        self.0.iter().find_map(|predicate| predicate.violation(sample))
+    }
+
+    /// Amounts at which `violation` can change, i.e. the lowest amount of every band
+    /// the predicates split amounts into.
+    pub fn amount_thresholds(&self, thresholds: &mut Vec<u64>) {
+        for predicate in &self.0 {
+            match predicate {
+                Predicate::AmountLessThan(amount) => thresholds.push(*amount),
+                Predicate::AmountGreaterThan(amount) => thresholds.push(amount.saturating_add(1)),
+                Predicate::AmountBetween { min, max } => thresholds.extend([*min, max.saturating_add(1)]),
+                Predicate::All(rules) | Predicate::Any(rules) => {
+                    rules.iter().for_each(|rule| rule.amount_thresholds(thresholds))
+                }
+                Predicate::Not(rule) => rule.amount_thresholds(thresholds),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Predicate {
+    /// Why the predicate doesn't hold for the sample, e.g. "amount 1650 ≥ 1500".
+    pub fn violation(&self, sample: &Sample) -> Option<String> {
+        let attribute_in = |name: &str, values: &[String]| match sample.attribute(name) {
+            Some(Value::String(value)) if values.contains(&value) => None,
+            value => Some(format!("{} {} not in {:?}", name, show(value), values)),
+        };
+        match self {
+            Predicate::AmountLessThan(amount) => {
+                (sample.amount >= *amount).then(|| format!("amount {} ≥ {}", sample.amount, amount))
+            }
+            Predicate::AmountGreaterThan(amount) => {
+                (sample.amount <= *amount).then(|| format!("amount {} ≤ {}", sample.amount, amount))
+            }
+            Predicate::AmountBetween { min, max } => (!(*min..=*max).contains(&sample.amount))
+                .then(|| format!("amount {} not between {} and {}", sample.amount, min, max)),
+            Predicate::CurrencyIn(currencies) => (!currencies.contains(&sample.currency))
+                .then(|| format!("currency {:?} not in {:?}", sample.currency, currencies)),
+            Predicate::CurrencyNotIn(currencies) => currencies
+                .contains(&sample.currency)
+                .then(|| format!("currency {:?} excluded by {:?}", sample.currency, currencies)),
+            Predicate::CountryIn(countries) => attribute_in("country", countries),
+            Predicate::CardNetworkIn(networks) => attribute_in("card_network", networks),
+            Predicate::AuthType(auth_type) => match sample.attribute("auth_type") {
+                Some(Value::String(value)) if value == *auth_type => None,
+                value => Some(format!("auth_type {} ≠ {:?}", show(value), auth_type)),
+            },
+            Predicate::AttributeEquals(attributes) => sorted(attributes).into_iter().find_map(|(name, expected)| {
+                let value = sample.attribute(&name.0);
+                (value.as_ref() != Some(expected)).then(|| format!("{} {} ≠ {}", name.0, show(value), expected))
+            }),
+            Predicate::AttributeIn(attributes) => sorted(attributes).into_iter().find_map(|(name, values)| {
+                let value = sample.attribute(&name.0);
+                if value.as_ref().is_some_and(|value| values.contains(value)) {
+                    return None;
+                }
+                let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                Some(format!("{} {} not in [{}]", name.0, show(value), values.join(", ")))
+            }),
+            Predicate::All(rules) => rules.iter().find_map(|rule| rule.violation(sample)),
+            Predicate::Any(rules) => {
+                let violations: Option<Vec<String>> = rules.iter().map(|rule| rule.violation(sample)).collect();
+                violations.map(|violations| format!("no `any` rule holds ({})", violations.join("; ")))
+            }
+            Predicate::Not(rule) => rule.violation(sample).is_none().then(|| "excluded by a `not` rule".to_string()),
+        }
+    }
+}
+
+fn show(value: Option<Value>) -> String {
+    value.map_or_else(|| "missing".to_string(), |value| value.to_string())
+}
+
+impl TryFrom<Map<String, Value>> for Behaviours {
+    type Error = String;
+
+    fn try_from(map: Map<String, Value>) -> Result<Self, Self::Error> {
+        let mut predicates = Vec::new();
+        for (name, argument) in map {
+            let predicate = Value::Object(Map::from_iter([(name.clone(), argument)]));
+            predicates.push(
+                serde_json::from_value(predicate)
+                    .map_err(|err| format!("invalid supported_behaviours predicate `{}`: {}", name, err))?,
+            );
+        }
+        Ok(Behaviours(predicates))
+    }
+}
+
+impl From<Behaviours> for Map<String, Value> {
+    fn from(behaviours: Behaviours) -> Self {
+        behaviours
+            .0
+            .into_iter()
+            .filter_map(|predicate| match serde_json::to_value(predicate) {
+                Ok(Value::Object(map)) => Some(map),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A 16.50 USD credit card payment from Germany.
+    fn sample() -> Sample {
+        Sample {
+            amount: 1650,
+            currency: "USD".to_string(),
+            payment_method: Key("card".to_string()),
+            payment_method_type: Some(Key("credit".to_string())),
+            attributes: HashMap::from([(Key("country".to_string()), json!("DE"))]),
+            timestamp_ms: 0,
+        }
+    }
+
+    fn behaviours(rules: Value) -> Behaviours {
+        serde_json::from_value(rules).unwrap()
+    }
+
+    #[test]
+    fn predicates_of_a_rule_must_all_hold() {
+        assert_eq!(behaviours(json!({"amount_less_than": 2000, "country_in": ["DE", "FR"]})).violation(&sample()), None);
+        assert_eq!(
+            behaviours(json!({"amount_less_than": 1500})).violation(&sample()),
+            Some("amount 1650 ≥ 1500".to_string())
+        );
+        assert_eq!(
+            behaviours(json!({"attribute_equals": {"payment_method_type": "debit"}})).violation(&sample()),
+            Some("payment_method_type \"credit\" ≠ \"debit\"".to_string())
+        );
+        assert_eq!(
+            behaviours(json!({"auth_type": "3ds"})).violation(&sample()),
+            Some("auth_type missing ≠ \"3ds\"".to_string())
+        );
+    }
+
+    #[test]
+    fn all_any_and_not_combine_rules() {
+        let all = behaviours(json!({"all": [{"currency_in": ["USD"]}, {"amount_between": {"min": 0, "max": 1000}}]}));
+        assert_eq!(all.violation(&sample()), Some("amount 1650 not between 0 and 1000".to_string()));
+
+        let any = behaviours(json!({"any": [{"amount_less_than": 1500}, {"country_in": ["DE"]}]}));
+        assert_eq!(any.violation(&sample()), None);
+        let any = behaviours(json!({"any": [{"amount_less_than": 1500}, {"currency_in": ["EUR"]}]}));
+        assert_eq!(
+            any.violation(&sample()),
+            Some("no `any` rule holds (amount 1650 ≥ 1500; currency \"USD\" not in [\"EUR\"])".to_string())
+        );
+
+        let not = behaviours(json!({"not": {"country_in": ["DE"]}}));
+        assert_eq!(not.violation(&sample()), Some("excluded by a `not` rule".to_string()));
+        let not = behaviours(json!({"not": {"country_in": ["FR"]}}));
+        assert_eq!(not.violation(&sample()), None);
+    }
+
+    #[test]
+    fn unknown_predicates_fail_to_parse() {
+        let error = serde_json::from_value::<Behaviours>(json!({"amount_below": 1500})).unwrap_err().to_string();
+        assert!(
+            error.starts_with("invalid supported_behaviours predicate `amount_below`: unknown variant `amount_below`"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn amount_thresholds_start_every_band() {
+        let mut thresholds = Vec::new();
+        behaviours(json!({"any": [{"amount_less_than": 1500}, {"amount_between": {"min": 2000, "max": 5000}}]}))
+            .amount_thresholds(&mut thresholds);
+        thresholds.sort();
+        assert_eq!(thresholds, vec![1500, 2000, 5001]);
+    }
+}
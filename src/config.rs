@@ -1,32 +1,71 @@
 use serde::{Deserialize, Serialize};
 use core::ops::Deref;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
 use crate::sampler::Sampler;
-use std::path::Path;
-use anyhow::{ensure, Context, Result};
-use rand::Rng;
+use crate::behaviour::Behaviours;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
 use serde_json::Value;
+use rand::Rng;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     pub user: UserSimulationConfig,
-    pub psp: PspSimulationConfig,
-    pub merchant: MerchantConfig,
+    /// Shared with the `oracle` router, which reads the true success rates from it.
+    pub psp: Rc<PspSimulationConfig>,
+    /// Shared with the `oracle` router for the connectors' timeouts.
+    pub merchant: Rc<MerchantConfig>,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub feedback: FeedbackConfig,
+    /// Label every transaction with the best connector according to the PSP config
+    /// and classify the routing decision against it.
+    #[serde(default)]
+    pub classification: bool,
+    /// Seed of the simulation's random streams; a random one is used when absent.
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub arrivals: ArrivalProcess,
 }
 
 impl Config {
+    /// Load the config from `$CONFIG_FILE`, falling back to `./input.json` and `./config.json`.
     pub fn load() -> Result<Self> {
-        let default_path = Path::new("input.json");
-        if default_path.exists() {
-            let output = Self::load_from_path(default_path)?;
-            output.user.validate()?;
-            return Ok(output);
+        Self::load_from(Self::default_path()?)
+    }
+
+    /// `$CONFIG_FILE`, else the first of `./input.json` and `./config.json` that exists.
+    pub fn default_path() -> Result<PathBuf> {
+        if let Ok(path) = std::env::var("CONFIG_FILE") {
+            return Ok(PathBuf::from(path));
+        }
+        for default_path in ["input.json", "config.json"] {
+            if Path::new(default_path).exists() {
+                return Ok(PathBuf::from(default_path));
+            }
         }
 
-        anyhow::bail!("No config file found. Please provide it either in ./config.json or set `CONFIG_FILE` environment variable")
+        anyhow::bail!("No config file found. Please provide it either in ./input.json, ./config.json or set `CONFIG_FILE` environment variable")
     }
 
-    fn load_from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+    /// Load the config from `path` and fail with every problem `validate` finds.
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let output = Self::load_unvalidated(path)?;
+        let problems = output.validate();
+        if !problems.is_empty() {
+            let problems: Vec<String> = problems.iter().map(|problem| format!("  {}", problem)).collect();
+            anyhow::bail!("Invalid config:\n{}", problems.join("\n"));
+        }
+        Ok(output)
+    }
+
+    /// Parse the config from `path` without validating it.
+    pub fn load_unvalidated<P: AsRef<Path>>(path: P) -> Result<Self> {
         let config_str = std::fs::read_to_string(path.as_ref())
             .with_context(|| format!("Failed to read config file: {:?}", path.as_ref()))?;
 
@@ -34,25 +73,103 @@ impl Config {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Hash)]
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Ord, PartialOrd, Clone, Hash)]
 #[serde(transparent)]
 pub struct Key(pub String);
 
+/// Entries of `map` ordered by key, for output and checks that must not depend on
+/// `HashMap` iteration order.
+pub(crate) fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> Vec<(&K, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UserSimulationConfig {
     #[serde(default = "default_amount")]
     pub amount: Option<AmountRange>,
     pub currency: Option<String>,
+    /// Weighted currency mix payments are drawn in, instead of the single `currency`.
+    pub currencies: Option<HashMap<Key, CurrencyDetails>>,
     #[serde(flatten)]
     pub payment_methods: SimulationConfig,
     pub extra_fields: Option<HashMap<Key, Value>>
 }
 
+/// Amount bounds in minor units.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AmountRange { pub min: u32, pub max: u32}
 
+const DEFAULT_AMOUNT: AmountRange = AmountRange { min: 0, max: 2000 };
+
 fn default_amount() -> Option<AmountRange> {
-    Some(AmountRange { min: 0, max: 2000 })
+    Some(DEFAULT_AMOUNT)
+}
+
+/// One currency of the user's `currencies` mix.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CurrencyDetails {
+    pub percentage: u8,
+    /// Amount bounds of payments in this currency, in its minor units; the user's
+    /// `amount` when absent.
+    pub amount: Option<AmountRange>,
+    /// Decimal places of the currency, e.g. 2 for USD and 0 for JPY; only used to
+    /// print amounts in the coverage report.
+    #[serde(default = "default_minor_units")]
+    pub minor_units: u32,
+}
+
+fn default_minor_units() -> u32 {
+    2
+}
+
+/// A currency payments are drawn in, with its share of the traffic between 0 and 1.
+#[derive(Debug)]
+pub struct CurrencyShare<'a> {
+    pub currency: String,
+    pub share: f64,
+    pub amount: &'a AmountRange,
+    pub minor_units: u32,
+}
+
+impl UserSimulationConfig {
+    /// The `currencies` mix in currency order, or the single `currency` (USD by
+    /// default) when there is none.
+    pub fn currency_mix(&self) -> Vec<CurrencyShare<'_>> {
+        let default_amount = self.amount.as_ref().unwrap_or(&DEFAULT_AMOUNT);
+        let Some(currencies) = &self.currencies else {
+            return vec![CurrencyShare {
+                currency: self.currency.clone().unwrap_or_else(|| "USD".to_string()),
+                share: 1.0,
+                amount: default_amount,
+                minor_units: default_minor_units(),
+            }];
+        };
+        sorted(currencies)
+            .into_iter()
+            .map(|(currency, details)| CurrencyShare {
+                currency: currency.0.clone(),
+                share: details.percentage as f64 / 100.0,
+                amount: details.amount.as_ref().unwrap_or(default_amount),
+                minor_units: details.minor_units,
+            })
+            .collect()
+    }
+
+    /// Draw the currency of a payment and its amount range. Nothing is drawn without
+    /// a `currencies` mix, so single-currency configs keep their sample streams.
+    fn choose_currency<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<(String, &AmountRange)> {
+        let mut number = if self.currencies.is_some() { rng.gen_range(0..100) } else { 0 };
+        for CurrencyShare { currency, share, amount, .. } in self.currency_mix() {
+            let percentage = (share * 100.0).round() as u32;
+            if number < percentage {
+                return Ok((currency, amount));
+            }
+            number -= percentage;
+        }
+        Err(anyhow!("No currency found"))
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -60,19 +177,76 @@ fn default_amount() -> Option<AmountRange> {
 pub struct SimulationConfig(HashMap<Key, PaymentMethods>);
 
 impl Sampler for UserSimulationConfig {
-    fn generate_sample(&self) -> Result<HashMap<Key, Key>> {
-        let amt = Self::generate_random_amount(&self.amount);
-        let binding = "USD".to_string();
-        let currency = self.currency.as_ref().unwrap_or(&binding);
-        let mut sample = HashMap::new();
-        sample.insert(Key("amount".to_string()), Key(amt.to_string()));
-        sample.insert(Key("currency".to_string()), Key(currency.clone()));
-        let res = Self::list_payment_methods(&self.payment_methods);
-        res.map(|payment_methods| {
-            sample.extend(payment_methods.into_iter().map(|(k, v)| (k.to_owned(), v.to_owned())));
-            sample
+    fn generate_sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Sample> {
+        let (currency, amount_range) = self.choose_currency(rng)?;
+        let amount = Self::generate_random_amount(amount_range, rng);
+        let mut attributes = self.extra_fields.clone().unwrap_or_default();
+        attributes.extend(Self::list_payment_methods(&self.payment_methods, rng)?);
+
+        Sample::from_attributes(amount, currency, attributes)
+    }
+}
+
+/// A simulated payment, as produced by the `Sampler`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Sample {
+    /// Amount in minor units of `currency`.
+    pub amount: u64,
+    pub currency: String,
+    pub payment_method: Key,
+    pub payment_method_type: Option<Key>,
+    /// Every other sampled dimension and `extra_fields` entry, e.g. `auth_type`.
+    #[serde(default)]
+    pub attributes: HashMap<Key, Value>,
+    /// Simulated arrival time in milliseconds since the start of the run.
+    #[serde(default)]
+    pub timestamp_ms: u64,
+}
+
+impl Sample {
+    /// Sample whose payment method and type are taken out of the sampled `attributes`.
+    pub fn from_attributes(amount: u64, currency: String, mut attributes: HashMap<Key, Value>) -> Result<Self> {
+        let mut take = |name: &str| {
+            attributes.remove(&Key(name.to_string())).map(|value| match value {
+                Value::String(value) => Key(value),
+                other => Key(other.to_string()),
+            })
+        };
+        let payment_method = take("payment_methods")
+            .ok_or_else(|| anyhow!("User config has no `payment_methods` to sample from"))?;
+        let payment_method_type = take("payment_method_type");
+        Ok(Sample {
+            amount,
+            currency,
+            payment_method,
+            payment_method_type,
+            attributes,
+            timestamp_ms: 0,
         })
     }
+
+    /// Value of the sample dimension `name`: `amount`, `currency`, `payment_methods`,
+    /// `payment_method_type` or any other attribute.
+    pub fn attribute(&self, name: &str) -> Option<Value> {
+        match name {
+            "amount" => Some(Value::from(self.amount)),
+            "currency" => Some(Value::String(self.currency.clone())),
+            "payment_methods" => Some(Value::String(self.payment_method.0.clone())),
+            "payment_method_type" => self.payment_method_type.as_ref().map(|key| Value::String(key.0.clone())),
+            _ => self.attributes.get(&Key(name.to_string())).cloned(),
+        }
+    }
+
+    /// Payment method and payment method type, as tracked by `Metrics`.
+    /// A missing payment method type is reported as "N/A".
+    pub fn payment_method_dimensions(&self) -> (String, String) {
+        let payment_method_type = self
+            .payment_method_type
+            .as_ref()
+            .map(|value| value.0.clone())
+            .unwrap_or_else(|| "N/A".to_string());
+        (self.payment_method.0.clone(), payment_method_type)
+    }
 }
 
 impl Deref for SimulationConfig {
@@ -108,51 +282,73 @@ pub enum PaymentMethodDetails {
 }
 
 
-impl PaymentMethods {
-    pub fn validate(&self) -> Result<()> {
-        let mut total = 0;
-        for (_key, value) in self.0.iter() {
-            match value {
-                &PaymentMethodDetails::Percentage(value) => total += value,
-                &PaymentMethodDetails::Composite { percentage, ref next , extra_fields: _} => {
-                    total += percentage;
-                    next.validate()?;
-                }
-            }
-        }
-        ensure!(total == 100, "Total percentage must be 100");
-        Ok(())
-    }
-}
-
-impl SimulationConfig {
-    pub fn validate(&self) -> Result<()> {
-        self.0.iter().try_for_each(|(key, value)| {
-            value
-                .validate()
-                .context(format!("validation failed for: {}", key.0))?;
-            Ok(())
-        })
-    }
-}
-
-impl UserSimulationConfig {
-    pub fn validate(&self) -> Result<()> {
-        self.payment_methods.validate()
-    }
-}
-
 //psp structs
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PspSimulationConfig {
     pub psp_variants: HashMap<Key, PspDetails>,
-    pub otherwise: Option<String>
+    pub otherwise: Option<String>,
+    /// Seed of the random incidents of `sr_schedule`s; set by the simulation from its own seed.
+    #[serde(skip)]
+    pub seed: u64,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PspDetails {
     pub payment_methods: HashMap<Key, PaymentMethodTypes>,
     pub psp_time_config: Option<PspTimeConfig>,
+    /// payment method (or "*" for any other) -> failure reason -> weight
+    #[serde(default)]
+    pub failure_reasons: HashMap<Key, HashMap<Key, u32>>,
+    /// Changes to the configured success rates over the run, applied in order.
+    #[serde(default)]
+    pub sr_schedule: Vec<SrChange>,
+    /// currency -> payment method -> success rates, overriding `payment_methods` for
+    /// payments in that currency
+    #[serde(default)]
+    pub currencies: HashMap<Key, HashMap<Key, PaymentMethodTypes>>,
+}
+
+impl PspDetails {
+    /// Weighted failure reasons declared for `payment_method`, falling back to "*".
+    pub fn failure_reasons_for(&self, payment_method: &Key) -> Option<&HashMap<Key, u32>> {
+        self.failure_reasons
+            .get(payment_method)
+            .or_else(|| self.failure_reasons.get(&Key("*".to_string())))
+    }
+}
+
+/// Failure reasons that are a decision about the payer rather than a problem of
+/// the PSP or issuer systems.
+pub const USER_DECLINE_REASONS: &[&str] = &[
+    "insufficient_funds",
+    "do_not_honor",
+    "fraud_suspected",
+    "card_expired",
+    "invalid_card",
+    "authentication_failed",
+];
+
+/// Who a failed payment is attributable to.
+#[derive(Debug, Serialize, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum FailureCategory {
+    /// Declined because of the payer, e.g. insufficient funds; no routing choice would have helped.
+    User,
+    /// Failed because of the PSP or issuer systems, including timeouts.
+    Psp,
+    /// The PSP config declares no failure reasons for the payment method.
+    Unknown,
+}
+
+impl FailureCategory {
+    pub fn of(reason: &str) -> Self {
+        if reason == "unspecified" {
+            FailureCategory::Unknown
+        } else if USER_DECLINE_REASONS.contains(&reason) {
+            FailureCategory::User
+        } else {
+            FailureCategory::Psp
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -162,19 +358,149 @@ pub enum PaymentMethodTypes {
     Simple { sr: u32 },
 }
 
+impl PaymentMethodTypes {
+    fn success_rate(&self, sample: &Sample) -> Option<u32> {
+        match self {
+            PaymentMethodTypes::PaymentTypes(details) => details
+                .iter()
+                .find(|detail| {
+                    detail.payment_method_type.0 == "*"
+                        || sample.payment_method_type.as_ref() == Some(&detail.payment_method_type)
+                })
+                .map(|detail| detail.sr),
+            PaymentMethodTypes::Simple { sr } => Some(*sr),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PaymentTypeDetails {
     pub payment_method_type: Key,
     pub sr: u32,
 }
 
+/// Point of the run a transaction is evaluated at.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SimTime {
+    /// Simulated time since the start of the run.
+    pub elapsed: Duration,
+    /// Index of the payment in the run.
+    pub transaction: u64,
+}
+
+/// One entry of a PSP's `sr_schedule`, optionally limited to a payment method.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SrChange {
+    #[serde(flatten)]
+    pub change: SrChangeKind,
+    /// What `start`, `end`, `period`, ... are measured in.
+    #[serde(default)]
+    pub clock: ScheduleClock,
+    pub payment_method: Option<Key>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleClock {
+    /// Milliseconds of simulated time.
+    #[default]
+    Time,
+    /// Number of payments.
+    Transactions,
+}
+
+/// Success rates are in percent; points and lengths are in units of the entry's `clock`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum SrChangeKind {
+    /// Success rate becomes `sr` from `start` on.
+    Step { start: u64, sr: f64 },
+    /// Every transaction fails between `start` and `end`, whatever the other entries say.
+    Outage { start: u64, end: u64 },
+    /// Success rate moves linearly to `to` between `start` and `end` and stays there.
+    Drift { start: u64, end: u64, to: f64 },
+    /// Success rate swings by up to `amplitude` points over each `period`, e.g. daily.
+    Periodic {
+        period: u64,
+        amplitude: f64,
+        #[serde(default)]
+        phase: u64,
+    },
+    /// Each `every` long interval has an incident with `probability`, starting at
+    /// a random point and lasting `duration`, during which the success rate is `sr`.
+    Incidents { every: u64, probability: f64, duration: u64, sr: f64 },
+}
+
+/// Response time of a PSP in milliseconds.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PspTimeConfig {
     pub mean: u32,
     pub stddev: u32,
+    #[serde(default)]
+    pub distribution: LatencyDistribution,
+    /// Occasional very slow responses on top of the distribution.
+    pub tail: Option<LatencyTail>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyDistribution {
+    #[default]
+    Normal,
+    /// Log-normal with the configured `mean` and `stddev`, skewed towards slow responses.
+    LogNormal,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct LatencyTail {
+    /// Share of responses, in percent, that hit the tail.
+    pub percentage: f64,
+    /// Factor applied to the latency of those responses.
+    pub multiplier: f64,
 }
 
 impl PspSimulationConfig {
+    /// Configured success rate (in percent) of `connector` for the sample, if the
+    /// connector has a matching payment method.
+    /// The connector's `currencies` entry for the sample's currency takes precedence.
+    pub fn success_rate(&self, connector: &Key, sample: &Sample) -> Option<u32> {
+        let config = self.psp_variants.get(connector)?;
+        config
+            .currencies
+            .get(&Key(sample.currency.clone()))
+            .and_then(|payment_methods| payment_methods.get(&sample.payment_method)?.success_rate(sample))
+            .or_else(|| config.payment_methods.get(&sample.payment_method)?.success_rate(sample))
+    }
+
+    /// Probability of success of `connector` for the sample at `at`, falling back to
+    /// `otherwise`, times the probability that it answers within `timeout`.
+    pub fn expected_success(&self, connector: &Key, sample: &Sample, at: SimTime, timeout: Option<Duration>) -> f64 {
+        let success = match self.success_rate_at(connector, sample, at) {
+            Some(sr) => sr / 100.0,
+            None if self.default_status() == Status::Success => 1.0,
+            None => 0.0,
+        };
+        let time_config = self.psp_variants.get(connector).and_then(|details| details.psp_time_config.as_ref());
+        let in_time = match (timeout, time_config) {
+            (Some(timeout), Some(time_config)) => time_config.within(timeout),
+            _ => 1.0,
+        };
+        success * in_time
+    }
+
+    /// Eligible connector with the highest expected success for the sample at `at`,
+    /// given the merchant's timeouts; the first one in `connectors` wins ties.
+    pub fn best_connector(&self, connectors: &[Key], sample: &Sample, at: SimTime, merchant: &MerchantConfig) -> Option<Key> {
+        let mut best: Option<(&Key, f64)> = None;
+        for connector in connectors {
+            let expected = self.expected_success(connector, sample, at, merchant.timeout(connector));
+            if best.is_none_or(|(_, best_expected)| expected > best_expected) {
+                best = Some((connector, expected));
+            }
+        }
+        best.map(|(connector, _)| connector.clone())
+    }
+
     pub fn default_status(&self) -> Status {
         match self.otherwise {
             Some(ref status) => {
@@ -197,110 +523,521 @@ pub struct MerchantConfig {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ConnectorDetails {
-    pub supported_payment_methods: HashMap<Key, PaymentMethodConfig>
+    pub supported_payment_methods: HashMap<Key, PaymentMethodConfig>,
+    /// Timeout in milliseconds for this connector, overriding the merchant's `time_config`.
+    pub time_config: Option<u64>,
+    /// Currencies the connector accepts; any currency when absent.
+    pub supported_currencies: Option<Vec<String>>,
+}
+
+impl MerchantConfig {
+    /// How long the merchant waits for `connector` before giving up on a payment:
+    /// the connector's `time_config`, else `extra_fields.time_config`, in milliseconds.
+    pub fn timeout(&self, connector: &Key) -> Option<Duration> {
+        self.connectors_list
+            .get(connector)
+            .and_then(|details| details.time_config)
+            .or_else(|| {
+                self.extra_fields
+                    .as_ref()?
+                    .get(&Key("time_config".to_string()))?
+                    .as_u64()
+            })
+            .map(Duration::from_millis)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PaymentMethodConfig {
     pub payment_method_types: Option<Vec<String>>,
-    pub supported_behaviours: Option<HashMap<Key, Value>>
+    pub supported_behaviours: Option<Behaviours>
 }
 
-pub fn find_suitable_connectors (
-    sample: &HashMap<Key, Key>,
-    merchant_config: &MerchantConfig) -> Vec<Key> {
-        let mut suitable_connectors = Vec::new();
-        
-        for (connector_key, connector_details) in &merchant_config.connectors_list {
-            let mut is_suitable = false;
-            for(payment_method_key, payment_method_config) in &connector_details.supported_payment_methods {
-                let res = sample.get(&Key("payment_methods".to_string()));
-                match res {
-                    Some(payment_method) => {
-                        if payment_method == payment_method_key {
-                            is_suitable = true;
-                            if sample.get(&Key("payment_method_type".to_string())).is_some() {
-                                let payment_method_type = sample.get(&Key("payment_method_type".to_string())).unwrap();
-                                if let Some(payment_method_types) = payment_method_config.payment_method_types.as_ref() {
-                                    if payment_method_types.contains(&payment_method_type.0) {
-                                        is_suitable = true;
-                                    } else {
-                                        is_suitable = false;
-                                    }
-                                }
-                            }
-                            if payment_method_config.supported_behaviours.is_some() {
-                                let res = payment_method_config.supported_behaviours.as_ref().unwrap().get(&Key("amount_less_than".to_string()));
-                                match res {
-                                    Some(amt) => {
-                                        let amount = sample.get(&Key("amount".to_string())).unwrap();
-                                        if amount.0.parse::<u32>().unwrap() < amt.as_u64().unwrap() as u32 {
-                                            is_suitable = true;
-                                        } else {
-                                            is_suitable = false;
-                                        }
-                                    }
-                                    None => {
-                                        continue;
-                                    }
-                                }
-                            }
-                            if is_suitable {
-                                suitable_connectors.push(connector_key.clone());
-                            }
-                        }
-                    }
-                    None => {
-                        continue;
-                    }
-                }
+//routing structs
+/// Routing algorithm used to pick a connector among the eligible ones,
+/// selected by the `algorithm` field of the `routing` block.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum RoutingConfig {
+    #[default]
+    StraightThrough,
+    SuccessRate {
+        #[serde(default)]
+        window: SlidingWindow,
+        #[serde(default = "default_exploration_percentage")]
+        exploration_percentage: u8,
+    },
+    EpsilonGreedy {
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+        #[serde(default = "default_one")]
+        decay: f64,
+        #[serde(default)]
+        min_epsilon: f64,
+    },
+    Ucb1 {
+        #[serde(default = "default_exploration_factor")]
+        exploration_factor: f64,
+    },
+    ThompsonSampling {
+        #[serde(default = "default_one")]
+        prior_alpha: f64,
+        #[serde(default = "default_one")]
+        prior_beta: f64,
+    },
+    /// Always picks the eligible connector with the highest true expected success,
+    /// given the PSP config and the merchant's timeouts; the ceiling other algorithms
+    /// are measured against.
+    Oracle,
+    /// Asks an external dynamic-routing service, using `fallback` when it is unavailable.
+    Dynamo {
+        decision_url: String,
+        feedback_url: Option<String>,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+        #[serde(default)]
+        fallback: Box<RoutingConfig>,
+    },
+}
+
+impl RoutingConfig {
+    /// Routing algorithm `name` with all of its options left at their defaults.
+    pub fn from_name(name: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::json!({ "algorithm": name }))
+            .with_context(|| format!("Invalid routing algorithm: {}", name))
+    }
+}
+
+fn default_exploration_percentage() -> u8 {
+    10
+}
+
+fn default_epsilon() -> f64 {
+    0.1
+}
+
+fn default_exploration_factor() -> f64 {
+    2.0
+}
+
+fn default_one() -> f64 {
+    1.0
+}
+
+fn default_timeout_ms() -> u64 {
+    500
+}
+
+/// How much history a success-rate window keeps per connector and payment method.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SlidingWindow {
+    LastTransactions(usize),
+    LastSeconds(u64),
+}
+
+impl Default for SlidingWindow {
+    fn default() -> Self {
+        SlidingWindow::LastTransactions(100)
+    }
+}
+
+/// When payments arrive on the simulated clock.
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(tag = "process", rename_all = "snake_case")]
+pub enum ArrivalProcess {
+    /// Each payment arrives when the previous one completes.
+    #[default]
+    Sequential,
+    /// Exactly `tps` payments per second.
+    Constant { tps: f64 },
+    /// `tps` payments per second on average, at random.
+    Poisson { tps: f64 },
+    /// Poisson arrivals whose rate follows a daily curve around `tps`, highest at
+    /// `peak_hour` and `amplitude` (0 to 1) times lower or higher than `tps` at the extremes.
+    Diurnal {
+        tps: f64,
+        #[serde(default = "default_diurnal_amplitude")]
+        amplitude: f64,
+        #[serde(default = "default_peak_hour")]
+        peak_hour: f64,
+    },
+    /// Poisson arrivals at `tps`, raised to `burst_tps` for the first `duration_ms`
+    /// of every `every_ms`.
+    Bursts { tps: f64, burst_tps: f64, every_ms: u64, duration_ms: u64 },
+}
+
+fn default_diurnal_amplitude() -> f64 {
+    0.5
+}
+
+fn default_peak_hour() -> f64 {
+    14.0
+}
+
+/// Whether and how a failed payment is retried on another connector.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RetryConfig {
+    /// Attempts per payment, including the first one; 1 disables retries.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Failure reasons worth retrying; "timeout" covers timed out attempts.
+    #[serde(default = "default_retryable_reasons")]
+    pub retryable_reasons: Vec<String>,
+    /// Whether a retry may go to a connector that already failed the payment.
+    #[serde(default)]
+    pub allow_same_connector: bool,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: default_max_attempts(),
+            retryable_reasons: default_retryable_reasons(),
+            allow_same_connector: false,
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn is_retryable(&self, status: &Status, failure_reason: Option<&Key>) -> bool {
+        let reason = match (status, failure_reason) {
+            (Status::Success, _) => return false,
+            (Status::Timeout, _) => "timeout",
+            (Status::Failure, Some(reason)) => reason.0.as_str(),
+            (Status::Failure, None) => return false,
+        };
+        self.retryable_reasons.iter().any(|retryable| retryable == reason)
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    1
+}
+
+fn default_retryable_reasons() -> Vec<String> {
+    ["issuer_unavailable", "technical_error", "timeout"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// When transaction outcomes are handed back to the routing algorithm.
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct FeedbackConfig {
+    #[serde(default)]
+    pub delay: FeedbackDelay,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackDelay {
+    /// Delivered before the next transaction is routed.
+    #[default]
+    Immediate,
+    /// Delivered once this many further transactions have been routed.
+    AfterTransactions(u64),
+    /// Delivered once this many simulated milliseconds have passed since the PSP responded.
+    AfterMs(u64),
+}
+
+/// Outcome of a routed transaction, as reported back to the routing algorithm.
+#[derive(Debug, Clone)]
+pub struct Feedback {
+    pub connector: Key,
+    pub sample: Sample,
+    pub status: Status,
+    pub latency: Duration,
+    /// Simulated time at which the PSP responded.
+    pub timestamp: Duration,
+}
+
+/// Result of simulating a transaction against a PSP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub status: Status,
+    pub latency: Duration,
+    /// Why a failed transaction failed, if the PSP config declares failure reasons.
+    pub failure_reason: Option<Key>,
+}
+
+impl Outcome {
+    /// Turn the outcome into a `Timeout` when the PSP took longer than `timeout`;
+    /// the merchant stops waiting, so the latency is capped at the timeout.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        match timeout {
+            Some(timeout) if self.latency > timeout => Outcome {
+                status: Status::Timeout,
+                latency: timeout,
+                failure_reason: None,
+            },
+            _ => self,
+        }
+    }
+}
+
+/// Whether a connector can take a sample, or the first check it fails, in the
+/// order `connector_eligibility` makes them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Eligibility {
+    PaymentMethodNotSupported(Key),
+    CurrencyNotSupported { currency: String, supported: Vec<String> },
+    PaymentMethodTypeNotSupported { payment_method_type: Key, supported: Vec<String> },
+    /// A `supported_behaviours` predicate doesn't hold, with the explanation.
+    BehavioursNotMet(String),
+    Eligible,
+}
+
+impl Eligibility {
+    /// Reason recorded for unroutable payments.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Eligibility::PaymentMethodNotSupported(_) => "payment_method_not_supported",
+            Eligibility::CurrencyNotSupported { .. } => "currency_not_supported",
+            Eligibility::PaymentMethodTypeNotSupported { .. } => "payment_method_type_not_supported",
+            Eligibility::BehavioursNotMet(_) => "supported_behaviours_not_met",
+            Eligibility::Eligible => "eligible",
+        }
+    }
+}
+
+impl std::fmt::Display for Eligibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Eligibility::PaymentMethodNotSupported(payment_method) => {
+                write!(f, "payment method {:?} not supported", payment_method.0)
+            }
+            Eligibility::CurrencyNotSupported { currency, supported } => {
+                write!(f, "currency {:?} not in {:?}", currency, supported)
             }
+            Eligibility::PaymentMethodTypeNotSupported { payment_method_type, supported } => {
+                write!(f, "payment method type {:?} not in {:?}", payment_method_type.0, supported)
+            }
+            Eligibility::BehavioursNotMet(violation) => write!(f, "{}", violation),
+            Eligibility::Eligible => write!(f, "eligible"),
         }
-        suitable_connectors
+    }
 }
 
-pub struct StraightThroughRouting{
-    pub connectors: Vec<Key>
+/// Eligibility of every connector of the merchant for the sample, by connector name.
+pub fn connector_eligibility(sample: &Sample, merchant_config: &MerchantConfig) -> Vec<(Key, Eligibility)> {
+    let mut eligibility: Vec<_> = merchant_config
+        .connectors_list
+        .iter()
+        .map(|(connector_key, connector_details)| (connector_key.clone(), connector_details.eligibility(sample)))
+        .collect();
+    // Keep a stable order regardless of the HashMap iteration order.
+    eligibility.sort();
+    eligibility
 }
-impl StraightThroughRouting{
-    pub fn get_connector(&self) -> Key{
-        let mut rng = rand::thread_rng();
-        self.connectors[rng.gen_range(0..self.connectors.len())].clone()
+
+impl ConnectorDetails {
+    pub fn eligibility(&self, sample: &Sample) -> Eligibility {
+        let Some(payment_method_config) = self.supported_payment_methods.get(&sample.payment_method) else {
+            return Eligibility::PaymentMethodNotSupported(sample.payment_method.clone());
+        };
+        if let Some(supported_currencies) = &self.supported_currencies {
+            if !supported_currencies.contains(&sample.currency) {
+                return Eligibility::CurrencyNotSupported {
+                    currency: sample.currency.clone(),
+                    supported: supported_currencies.clone(),
+                };
+            }
+        }
+        if let (Some(payment_method_type), Some(payment_method_types)) =
+            (&sample.payment_method_type, payment_method_config.payment_method_types.as_ref())
+        {
+            if !payment_method_types.contains(&payment_method_type.0) {
+                return Eligibility::PaymentMethodTypeNotSupported {
+                    payment_method_type: payment_method_type.clone(),
+                    supported: payment_method_types.clone(),
+                };
+            }
+        }
+        match payment_method_config.supported_behaviours.as_ref().and_then(|behaviours| behaviours.violation(sample)) {
+            Some(violation) => Eligibility::BehavioursNotMet(violation),
+            None => Eligibility::Eligible,
+        }
+    }
+}
+
+pub fn find_suitable_connectors (
+    sample: &Sample,
+    merchant_config: &MerchantConfig) -> Vec<Key> {
+        connector_eligibility(sample, merchant_config)
+            .into_iter()
+            .filter(|(_, eligibility)| *eligibility == Eligibility::Eligible)
+            .map(|(connector_key, _)| connector_key)
+            .collect()
+}
+
+/// Why no connector can take a payment, given the `connector_eligibility` of every
+/// connector: the furthest any of them got through the checks.
+pub fn unroutable_reason(eligibility: &[(Key, Eligibility)]) -> &'static str {
+    eligibility
+        .iter()
+        .map(|(_, eligibility)| eligibility)
+        .max()
+        .map_or("payment_method_not_supported", Eligibility::code)
+}
+
+impl MerchantConfig {
+    /// Amounts at which the eligibility of some connector changes, in ascending order.
+    pub fn amount_thresholds(&self) -> Vec<u64> {
+        let mut thresholds = Vec::new();
+        self.connectors_list
+            .values()
+            .flat_map(|details| details.supported_payment_methods.values())
+            .filter_map(|config| config.supported_behaviours.as_ref())
+            .for_each(|behaviours| behaviours.amount_thresholds(&mut thresholds));
+        thresholds.sort_unstable();
+        thresholds.dedup();
+        thresholds
     }
-    
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Hash, Clone)]
 pub enum Status {
     Success,
-    Failure
+    Failure,
+    /// The PSP didn't respond within the merchant's timeout.
+    Timeout,
 }
 
 pub struct PaymentRecorderData{
     pub connector: Key,
     pub verdict: Status,
-    pub payment_data: Key,
+    pub sample: Sample,
+    /// Best connector for the sample according to the PSP config, when classifying.
+    pub label: Option<Key>,
+    pub latency: Duration,
+    pub failure_reason: Option<Key>,
+    /// 1 for the first attempt of a payment, 2 for its first retry, ...
+    pub attempt: u32,
+    /// Status of the payment's last attempt.
+    pub final_verdict: Status,
+    /// Simulated time the attempt was sent to the PSP.
+    pub timestamp: Duration,
+    /// Expected successes lost against the best eligible connector.
+    pub regret: f64,
 }
 impl PaymentRecorderData {
-    pub fn set_values(connector: Key, verdict: Status, payment_data: Key) -> Self {
+    pub fn set_values(connector: Key, verdict: Status, sample: Sample) -> Self {
         PaymentRecorderData {
             connector,
+            final_verdict: verdict.clone(),
             verdict,
-            payment_data,
+            sample,
+            label: None,
+            latency: Duration::ZERO,
+            failure_reason: None,
+            attempt: 1,
+            timestamp: Duration::ZERO,
+            regret: 0.0,
+        }
+    }
+
+    pub fn with_regret(mut self, regret: f64) -> Self {
+        self.regret = regret;
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: Duration) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_attempt(mut self, attempt: u32) -> Self {
+        self.attempt = attempt;
+        self
+    }
+
+    pub fn with_final_verdict(mut self, final_verdict: Status) -> Self {
+        self.final_verdict = final_verdict;
+        self
+    }
+
+    pub fn with_failure_reason(mut self, failure_reason: Option<Key>) -> Self {
+        self.failure_reason = failure_reason;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_label(mut self, label: Option<Key>) -> Self {
+        self.label = label;
+        self
+    }
+}
+
+/// Routing decision compared with the label, as described in the README:
+/// a positive is routing to the label, a true outcome is one whose status agrees.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum Classification {
+    #[serde(rename = "True Positive")]
+    TruePositive,
+    #[serde(rename = "False Negative")]
+    FalseNegative,
+    #[serde(rename = "False Positive")]
+    FalsePositive,
+    #[serde(rename = "True Negative")]
+    TrueNegative,
+}
+
+impl Classification {
+    pub fn classify(label: &Key, connector: &Key, verdict: &Status) -> Self {
+        match (label == connector, verdict) {
+            (true, Status::Success) => Classification::TruePositive,
+            (false, Status::Success) => Classification::FalseNegative,
+            (true, Status::Failure | Status::Timeout) => Classification::FalsePositive,
+            (false, Status::Failure | Status::Timeout) => Classification::TrueNegative,
         }
     }
-    
 }
-#[derive(Debug)]
+/// connector -> payment method -> payment method type -> status -> count
+pub type MetricsMap = HashMap<String, HashMap<String, HashMap<String, HashMap<Status, usize>>>>;
 
+#[derive(Debug, Default)]
 pub struct Metrics {
-    pub key: std::collections::HashMap<String, std::collections::HashMap<String, std::collections::HashMap<String, std::collections::HashMap<Status, usize>>>>,
+    pub key: MetricsMap,
+    /// label connector -> classification -> count
+    pub classification: HashMap<String, HashMap<Classification, usize>>,
+    /// connector -> payment method -> latencies in milliseconds
+    pub latencies: HashMap<String, HashMap<String, Vec<u64>>>,
+    /// connector -> payment method -> failure reason -> count, for failed and timed out transactions
+    pub failure_reasons: HashMap<String, HashMap<String, HashMap<String, usize>>>,
+    pub payments: PaymentCounts,
+    /// Width of the time buckets of `timeline`; no timeline is kept without it.
+    pub bucket: Option<Duration>,
+    /// time bucket -> connector -> transactions, successes
+    pub timeline: BTreeMap<u64, HashMap<String, (usize, usize)>>,
+    /// Cumulative regret after each transaction, in the order they were recorded.
+    pub regret: Vec<f64>,
+    /// payment method -> reason -> payments no connector could serve
+    pub unroutable: HashMap<String, HashMap<String, usize>>,
+    /// Seeds of the runs whose records the metrics were loaded from.
+    pub seeds: Vec<u64>,
+}
+
+/// Outcomes per payment rather than per attempt.
+#[derive(Debug, Default)]
+pub struct PaymentCounts {
+    pub total: usize,
+    pub attempts: usize,
+    pub first_attempt_success: usize,
+    pub final_success: usize,
 }
 
 impl Metrics {
    pub fn new() -> Self {
-        Metrics {
-            key: std::collections::HashMap::new(),
-        }
+        Self::default()
+    }
+
+    /// Metrics that also count transactions per `bucket` of simulated time.
+    pub fn with_buckets(bucket: Option<Duration>) -> Self {
+        Metrics { bucket, ..Metrics::default() }
     }
 }
\ No newline at end of file
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashMap};
+use anyhow::Result;
+use serde_json::Value;
+use crate::config::{find_suitable_connectors, sorted, Key, MerchantConfig, PaymentMethodDetails, Sample, SimulationConfig, UserSimulationConfig};
+
+/// Part of the expected traffic that shares a payment method, type, currency, amount
+/// band and set of eligible connectors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageRow {
+    pub payment_method: Key,
+    pub payment_method_type: Option<Key>,
+    pub currency: String,
+    /// Decimal places of `currency`, used to print the amounts.
+    pub minor_units: u32,
+    /// Inclusive amount bounds in minor units.
+    pub amount: (u64, u64),
+    /// Share of the expected traffic, between 0 and 1.
+    pub share: f64,
+    pub connectors: Vec<Key>,
+}
+
+/// Work out, without sampling, which connectors are eligible for every combination
+/// the user config can produce, weighted by how likely the combination is. Amounts are
+/// split into bands at the thresholds of the merchant's `supported_behaviours`.
+pub fn analyze_coverage(user: &UserSimulationConfig, merchant: &MerchantConfig) -> Result<Vec<CoverageRow>> {
+    let thresholds = merchant.amount_thresholds();
+    let combinations = combinations(&user.payment_methods);
+    // (payment method, type, currency, amount band, eligible connectors) -> share
+    type Group = (Key, Option<Key>, String, (u64, u64), Vec<Key>);
+    let mut rows: BTreeMap<Group, (u32, f64)> = BTreeMap::new();
+    for currency in user.currency_mix() {
+        let (min, max) = (currency.amount.min as u64, currency.amount.max as u64);
+        let mut bands = Vec::new();
+        let mut start = min;
+        for &threshold in &thresholds {
+            if threshold > start && threshold <= max {
+                bands.push((start, threshold - 1));
+                start = threshold;
+            }
+        }
+        bands.push((start, max));
+        let amounts = (max - min + 1) as f64;
+
+        for (probability, attributes) in &combinations {
+            let mut attributes = attributes.clone();
+            if let Some(extra_fields) = &user.extra_fields {
+                for (key, value) in extra_fields {
+                    attributes.entry(key.clone()).or_insert_with(|| value.clone());
+                }
+            }
+            for &(low, high) in &bands {
+                let sample = Sample::from_attributes(low, currency.currency.clone(), attributes.clone())?;
+                let connectors = find_suitable_connectors(&sample, merchant);
+                let share = currency.share * probability * (high - low + 1) as f64 / amounts;
+                let group = (sample.payment_method, sample.payment_method_type, sample.currency, (low, high), connectors);
+                rows.entry(group).or_insert((currency.minor_units, 0.0)).1 += share;
+            }
+        }
+    }
+    Ok(rows
+        .into_iter()
+        .map(|((payment_method, payment_method_type, currency, amount, connectors), (minor_units, share))| {
+            CoverageRow {
+                payment_method,
+                payment_method_type,
+                currency,
+                minor_units,
+                amount,
+                share,
+                connectors,
+            }
+        })
+        .collect())
+}
+
+/// Every choice of values the sampler can make, with its probability.
+fn combinations(config: &SimulationConfig) -> Vec<(f64, HashMap<Key, Value>)> {
+    let mut so_far = vec![(1.0, HashMap::new())];
+    for (dimension, choices) in sorted(config) {
+        let mut next_combinations = Vec::new();
+        for (probability, attributes) in &so_far {
+            for (choice, details) in choices.iter() {
+                let (percentage, nested) = match details {
+                    PaymentMethodDetails::Percentage(percentage) => (*percentage, None),
+                    PaymentMethodDetails::Composite { percentage, next, extra_fields } => {
+                        (*percentage, Some((next, extra_fields)))
+                    }
+                };
+                if percentage == 0 {
+                    continue;
+                }
+                let mut attributes = attributes.clone();
+                attributes.insert(dimension.clone(), Value::String(choice.0.clone()));
+                let probability = probability * percentage as f64 / 100.0;
+                match nested {
+                    None => next_combinations.push((probability, attributes)),
+                    Some((next, extra_fields)) => {
+                        if let Some(extra_fields) = extra_fields {
+                            attributes.extend(extra_fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+                        }
+                        for (nested_probability, nested_attributes) in combinations(next) {
+                            let mut attributes = attributes.clone();
+                            attributes.extend(nested_attributes);
+                            next_combinations.push((probability * nested_probability, attributes));
+                        }
+                    }
+                }
+            }
+        }
+        so_far = next_combinations;
+    }
+    so_far
+}
+
+pub fn print_coverage(rows: &[CoverageRow]) {
+    println!("Coverage of the expected traffic:");
+    let mut by_count = [0.0; 3];
+    for row in rows {
+        let connectors: Vec<String> = row.connectors.iter().map(|connector| format!("{:?}", connector.0)).collect();
+        println!(
+            "Payment Method: {:?}, Payment Method Type: {:?}, Currency: {:?}, Amount: {}-{}, Share: {:.2}%, Eligible connectors: {} ({})",
+            row.payment_method.0,
+            row.payment_method_type.as_ref().map_or("N/A", |key| key.0.as_str()),
+            row.currency,
+            format_amount(row.amount.0, row.minor_units),
+            format_amount(row.amount.1, row.minor_units),
+            row.share * 100.0,
+            row.connectors.len(),
+            connectors.join(", ")
+        );
+        by_count[row.connectors.len().min(2)] += row.share;
+    }
+    println!(
+        "Traffic with no eligible connector: {:.2}%, one: {:.2}%, multiple: {:.2}%",
+        by_count[0] * 100.0,
+        by_count[1] * 100.0,
+        by_count[2] * 100.0
+    );
+}
+
+/// Amount in minor units as a decimal number, e.g. 1999 with 2 minor units is "19.99".
+fn format_amount(amount: u64, minor_units: u32) -> String {
+    if minor_units == 0 {
+        return amount.to_string();
+    }
+    let scale = 10u64.pow(minor_units);
+    format!("{}.{:0width$}", amount / scale, amount % scale, width = minor_units as usize)
+}
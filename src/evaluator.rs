@@ -1,52 +1,315 @@
-use anyhow::Result;
-use crate::config::{Key, PspSimulationConfig, Status,};
-use crate::config::PaymentRecorderData;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use crate::config::{
+    sorted, Key, LatencyDistribution, Outcome, PspSimulationConfig, PspTimeConfig, Sample, ScheduleClock, SimTime,
+    SrChange, SrChangeKind, Status,
+};
+use crate::rng::incident_stream;
+use crate::stats::normal_cdf;
+use rand::distributions::WeightedIndex;
 use rand::Rng;
+use rand_distr::{Distribution, LogNormal, Normal};
 
 pub trait Evaluator {
-    fn call_evaluator(
-        &self,              
+    fn call_evaluator<R: Rng + ?Sized>(
+        &self,
         connector: &Key,
-        user_sample: &str,
-    ) -> Result<Status>;
+        sample: &Sample,
+        at: SimTime,
+        rng: &mut R,
+    ) -> Result<Outcome>;
 }
 
 impl Evaluator for PspSimulationConfig {
-    fn call_evaluator(
+    fn call_evaluator<R: Rng + ?Sized>(
+        &self,
+        connector: &Key,
+        sample: &Sample,
+        at: SimTime,
+        rng: &mut R,
+    ) -> Result<Outcome> {
+        let status = match self.success_rate_at(connector, sample, at) {
+            Some(sr) => {
+                let success = rng.gen_bool(sr / 100.0);
+                if success { Status::Success } else { Status::Failure }
+            }
+            // If no matching payment method is found, return default status
+            None => self.default_status(),
+        };
+        let failure_reason = match status {
+            Status::Success => None,
+            _ => self.failure_reason(connector, sample, rng)?,
+        };
+        let latency = match self
+            .psp_variants
+            .get(connector)
+            .and_then(|details| details.psp_time_config.as_ref())
+        {
+            Some(time_config) => time_config.sample_latency(rng)?,
+            None => Duration::ZERO,
+        };
+        Ok(Outcome { status, latency, failure_reason })
+    }
+}
+
+impl PspSimulationConfig {
+    /// Success rate (in percent) of `connector` for the sample at `at`: the configured
+    /// one with the connector's `sr_schedule` applied. An outage wins over every other
+    /// entry, wherever it is in the schedule.
+    pub fn success_rate_at(&self, connector: &Key, sample: &Sample, at: SimTime) -> Option<f64> {
+        let mut sr = self.success_rate(connector, sample)? as f64;
+        let details = &self.psp_variants[connector];
+        let mut outage = false;
+        for (index, change) in details.sr_schedule.iter().enumerate() {
+            if change.payment_method.as_ref().is_some_and(|pm| *pm != sample.payment_method) {
+                continue;
+            }
+            let schedule = format!("{}#{}", connector.0, index);
+            sr = change.apply(sr, at, self.seed, &schedule);
+            outage |= change.is_outage(at);
+        }
+        Some(if outage { 0.0 } else { sr.clamp(0.0, 100.0) })
+    }
+
+    /// Draw a reason for a failed transaction from the connector's weighted `failure_reasons`.
+    pub fn failure_reason<R: Rng + ?Sized>(
         &self,
         connector: &Key,
-        user_sample: &str,
-    ) -> Result<Status> {
-        let mut rng = rand::thread_rng();
-
-        if let Some(config) = self.psp_variants.get(&Key(connector.0.clone())) {
-            // Iterate over each payment method and handle both variants
-            for (pm_key, pm_value) in &config.payment_methods {
-                match pm_value {
-                    crate::config::PaymentMethodTypes::PaymentTypes(details) => {
-                        for detail in details {
-                            if user_sample.contains(&pm_key.0) &&
-                               (user_sample.contains(&detail.payment_method_type.0) || detail.payment_method_type.0 == "*")
-                            {
-                                let success = rng.gen_bool(detail.sr as f64 / 100.0);
-                                return Ok(if success { Status::Success } else { Status::Failure });
-                            }
-                        }
-                    },
-                    crate::config::PaymentMethodTypes::Simple { sr } => {
-                        if user_sample.contains(&pm_key.0) {
-                            let success = rng.gen_bool(*sr as f64 / 100.0);
-                            return Ok(if success { Status::Success } else { Status::Failure });
-                        }
+        sample: &Sample,
+        rng: &mut R,
+    ) -> Result<Option<Key>> {
+        let Some(reasons) = self
+            .psp_variants
+            .get(connector)
+            .and_then(|details| details.failure_reasons_for(&sample.payment_method))
+        else {
+            return Ok(None);
+        };
+        // Sort so that a seeded rng always picks the same reason.
+        let reasons = sorted(reasons);
+        let index = WeightedIndex::new(reasons.iter().map(|(_, weight)| **weight))
+            .with_context(|| format!("Invalid failure_reasons weights for {}", connector.0))?
+            .sample(rng);
+        Ok(Some(reasons[index].0.clone()))
+    }
+}
+
+impl SrChange {
+    /// Point of the run `at` is in this entry's `clock`.
+    fn now(&self, at: SimTime) -> u64 {
+        match self.clock {
+            ScheduleClock::Time => at.elapsed.as_millis() as u64,
+            ScheduleClock::Transactions => at.transaction,
+        }
+    }
+
+    /// Whether this entry is an outage that is on at `at`.
+    fn is_outage(&self, at: SimTime) -> bool {
+        matches!(self.change, SrChangeKind::Outage { start, end } if (start..end).contains(&self.now(at)))
+    }
+
+    /// Success rate at `at` given the one before this change; outages are left to
+    /// `success_rate_at`.
+    fn apply(&self, sr: f64, at: SimTime, seed: u64, schedule: &str) -> f64 {
+        let now = self.now(at);
+        match self.change {
+            SrChangeKind::Step { start, sr: new_sr } if now >= start => new_sr,
+            SrChangeKind::Drift { start, end, to } if now >= start => {
+                if now >= end {
+                    to
+                } else {
+                    sr + (to - sr) * (now - start) as f64 / (end - start) as f64
+                }
+            }
+            SrChangeKind::Periodic { period, amplitude, phase } if period > 0 => {
+                let cycle = ((now + phase) % period) as f64 / period as f64;
+                sr + amplitude * (cycle * std::f64::consts::TAU).sin()
+            }
+            SrChangeKind::Incidents { every, probability, duration, sr: incident_sr } if every > 0 => {
+                // Incidents may outlast their interval, so check every interval one
+                // covering `now` could have started in.
+                let first = now.saturating_sub(duration) / every;
+                for interval in first..=now / every {
+                    let mut rng = incident_stream(seed, schedule, interval);
+                    if !rng.gen_bool(probability.clamp(0.0, 1.0)) {
+                        continue;
+                    }
+                    let start = interval * every + rng.gen_range(0..every);
+                    if (start..start + duration).contains(&now) {
+                        return incident_sr;
                     }
                 }
+                sr
             }
-            // If no matching payment method is found, return default status
-            return Ok(self.default_status());
+            _ => sr,
         }
-        Ok(self.default_status())
     }
 }
 
+impl PspTimeConfig {
+    /// Probability that a response drawn by `sample_latency` takes at most `timeout`.
+    pub fn within(&self, timeout: Duration) -> f64 {
+        let cdf = |millis: f64| {
+            let (mean, stddev) = (self.mean as f64, self.stddev as f64);
+            match self.distribution {
+                LatencyDistribution::Normal if stddev > 0.0 => normal_cdf((millis - mean) / stddev),
+                LatencyDistribution::Normal => if mean <= millis { 1.0 } else { 0.0 },
+                LatencyDistribution::LogNormal if mean > 0.0 && millis > 0.0 => {
+                    let sigma_squared = (1.0 + (stddev * stddev) / (mean * mean)).ln();
+                    let mu = mean.ln() - sigma_squared / 2.0;
+                    normal_cdf((millis.ln() - mu) / sigma_squared.sqrt())
+                }
+                LatencyDistribution::LogNormal => if mean == 0.0 { 1.0 } else { 0.0 },
+            }
+        };
+        let millis = timeout.as_millis() as f64;
+        match &self.tail {
+            Some(tail) if tail.multiplier > 0.0 => {
+                let share = (tail.percentage / 100.0).clamp(0.0, 1.0);
+                (1.0 - share) * cdf(millis) + share * cdf(millis / tail.multiplier)
+            }
+            _ => cdf(millis),
+        }
+    }
 
+    /// Draw a response time from the configured distribution.
+    pub fn sample_latency<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Duration> {
+        let mean = self.mean as f64;
+        let stddev = self.stddev as f64;
+        let mut millis = match self.distribution {
+            LatencyDistribution::Normal => Normal::new(mean, stddev)?.sample(rng),
+            LatencyDistribution::LogNormal if mean > 0.0 => {
+                // Parameters of the underlying normal giving the configured mean and stddev.
+                let sigma_squared = (1.0 + (stddev * stddev) / (mean * mean)).ln();
+                let mu = mean.ln() - sigma_squared / 2.0;
+                LogNormal::new(mu, sigma_squared.sqrt())?.sample(rng)
+            }
+            LatencyDistribution::LogNormal => 0.0,
+        };
+        if let Some(tail) = &self.tail {
+            if rng.gen_bool((tail.percentage / 100.0).clamp(0.0, 1.0)) {
+                millis *= tail.multiplier;
+            }
+        }
+        Ok(Duration::from_millis(millis.max(0.0).round() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A stripe PSP with an 80% card success rate and the given `sr_schedule`.
+    fn psp(sr_schedule: serde_json::Value) -> PspSimulationConfig {
+        serde_json::from_value(json!({
+            "psp_variants": {"stripe": {"payment_methods": {"card": {"sr": 80}}, "sr_schedule": sr_schedule}}
+        }))
+        .unwrap()
+    }
+
+    fn card() -> Sample {
+        Sample {
+            amount: 1000,
+            currency: "USD".to_string(),
+            payment_method: Key("card".to_string()),
+            payment_method_type: None,
+            attributes: Default::default(),
+            timestamp_ms: 0,
+        }
+    }
+
+    fn sr_at(psp: &PspSimulationConfig, transaction: u64) -> f64 {
+        psp.success_rate_at(&Key("stripe".to_string()), &card(), at_transaction(transaction)).unwrap()
+    }
+
+    fn at_transaction(transaction: u64) -> SimTime {
+        SimTime { elapsed: Duration::ZERO, transaction }
+    }
+
+    fn change(json: &str) -> SrChange {
+        serde_json::from_str(json).unwrap()
+    }
 
+    #[test]
+    fn incidents_last_their_duration_across_interval_boundaries() {
+        let incidents = change(r#"{"change": "incidents", "every": 10, "probability": 1, "duration": 5, "sr": 0, "clock": "transactions"}"#);
+        let mut crossing = false;
+        for interval in 0..50 {
+            let mut rng = incident_stream(7, "stripe#0", interval);
+            assert!(rng.gen_bool(1.0));
+            let start = interval * 10 + rng.gen_range(0..10);
+            crossing |= start / 10 != (start + 4) / 10;
+            for transaction in start..start + 5 {
+                assert_eq!(incidents.apply(80.0, at_transaction(transaction), 7, "stripe#0"), 0.0, "transaction {}", transaction);
+            }
+        }
+        assert!(crossing, "no incident ran into the next interval");
+    }
+
+    #[test]
+    fn outages_win_over_later_entries() {
+        let psp = psp(json!([
+            {"change": "outage", "start": 0, "end": 100, "clock": "transactions"},
+            {"change": "periodic", "period": 40, "amplitude": 10, "clock": "transactions"},
+            {"change": "step", "start": 50, "sr": 90, "clock": "transactions"}
+        ]));
+        assert_eq!(sr_at(&psp, 10), 0.0);
+        assert_eq!(sr_at(&psp, 60), 0.0);
+        assert_eq!(sr_at(&psp, 100), 90.0);
+    }
+
+    #[test]
+    fn responses_within_timeout_follow_the_latency_distribution() {
+        let time_config: PspTimeConfig = serde_json::from_value(json!({"mean": 500, "stddev": 100})).unwrap();
+        assert!((time_config.within(Duration::from_millis(500)) - 0.5).abs() < 1e-4);
+        assert!((time_config.within(Duration::from_millis(700)) - 0.97725).abs() < 1e-4);
+        // 10% of responses are three times slower: 0.9 * P(x <= 700) + 0.1 * P(x <= 233.3).
+        let time_config: PspTimeConfig = serde_json::from_value(json!({
+            "mean": 500, "stddev": 100, "tail": {"percentage": 10, "multiplier": 3}
+        }))
+        .unwrap();
+        assert!((time_config.within(Duration::from_millis(700)) - 0.87991).abs() < 1e-4);
+    }
+
+    #[test]
+    fn steps_and_drifts_change_the_success_rate_from_their_start() {
+        let psp = psp(json!([
+            {"change": "step", "start": 10, "sr": 50, "clock": "transactions"},
+            {"change": "drift", "start": 20, "end": 30, "to": 100, "clock": "transactions"}
+        ]));
+        assert_eq!(sr_at(&psp, 9), 80.0);
+        assert_eq!(sr_at(&psp, 10), 50.0);
+        assert_eq!(sr_at(&psp, 20), 50.0);
+        assert_eq!(sr_at(&psp, 25), 75.0);
+        assert_eq!(sr_at(&psp, 30), 100.0);
+        assert_eq!(sr_at(&psp, 1000), 100.0);
+    }
+
+    #[test]
+    fn outages_cover_start_to_end() {
+        let psp = psp(json!([{"change": "outage", "start": 1000, "end": 2000}]));
+        let at = |elapsed: u64| SimTime { elapsed: Duration::from_millis(elapsed), transaction: 0 };
+        let sr = |elapsed| psp.success_rate_at(&Key("stripe".to_string()), &card(), at(elapsed)).unwrap();
+        assert_eq!(sr(999), 80.0);
+        assert_eq!(sr(1000), 0.0);
+        assert_eq!(sr(1999), 0.0);
+        assert_eq!(sr(2000), 80.0);
+    }
+
+    #[test]
+    fn periodic_changes_swing_around_the_success_rate() {
+        let psp = psp(json!([{"change": "periodic", "period": 40, "amplitude": 10, "clock": "transactions"}]));
+        assert!((sr_at(&psp, 0) - 80.0).abs() < 1e-9);
+        assert!((sr_at(&psp, 10) - 90.0).abs() < 1e-9);
+        assert!((sr_at(&psp, 30) - 70.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn incidents_never_happen_with_zero_probability() {
+        let psp = psp(json!([
+            {"change": "incidents", "every": 10, "probability": 0, "duration": 5, "sr": 0, "clock": "transactions"}
+        ]));
+        assert!((0..200).all(|transaction| sr_at(&psp, transaction) == 80.0));
+    }
+}
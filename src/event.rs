@@ -0,0 +1,70 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+/// Events ordered by simulated time; events due at the same time come out in the
+/// order they were scheduled.
+pub struct EventQueue<T> {
+    heap: BinaryHeap<Scheduled<T>>,
+    scheduled: u64,
+}
+
+struct Scheduled<T> {
+    time: Duration,
+    sequence: u64,
+    event: T,
+}
+
+impl<T> EventQueue<T> {
+    pub fn new() -> Self {
+        EventQueue {
+            heap: BinaryHeap::new(),
+            scheduled: 0,
+        }
+    }
+
+    pub fn schedule(&mut self, time: Duration, event: T) {
+        self.heap.push(Scheduled { time, sequence: self.scheduled, event });
+        self.scheduled += 1;
+    }
+
+    /// Earliest event and its time.
+    pub fn pop(&mut self) -> Option<(Duration, T)> {
+        self.heap.pop().map(|scheduled| (scheduled.time, scheduled.event))
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+impl<T> Default for EventQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// `BinaryHeap` is a max-heap, so the earliest event has to compare greatest.
+impl<T> Ord for Scheduled<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.time, other.sequence).cmp(&(self.time, self.sequence))
+    }
+}
+
+impl<T> PartialOrd for Scheduled<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for Scheduled<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.time, self.sequence) == (other.time, other.sequence)
+    }
+}
+
+impl<T> Eq for Scheduled<T> {}
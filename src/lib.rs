@@ -2,4 +2,14 @@ pub mod evaluator;
 pub mod sampler;
 pub mod config;
 pub mod recorder;
+pub mod router;
+pub mod mock_server;
+pub mod rng;
+pub mod simulation;
 
+pub mod arrival;
+pub mod event;
+pub mod stats;
+pub mod validation;
+pub mod coverage;
+pub mod behaviour;
@@ -0,0 +1,139 @@
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use tokio::sync::oneshot;
+use warp::http::StatusCode;
+use warp::Filter;
+use crate::config::Key;
+use crate::router::dynamo::{DecisionRequest, DecisionResponse, FeedbackRequest};
+
+/// How the mock decision server answers `POST /decide`.
+#[derive(Debug, Clone)]
+pub enum MockDecision {
+    /// The first eligible connector of the request.
+    First,
+    /// Always this connector, even if it isn't eligible.
+    Prefer(Key),
+    /// HTTP 500 without a body.
+    Error,
+}
+
+/// Local stand-in for a hyperswitch-dynamo compatible decision service, serving
+/// `POST /decide` and `POST /feedback` on an ephemeral localhost port from its own
+/// thread. The server shuts down when dropped.
+pub struct MockDynamoServer {
+    addr: SocketAddr,
+    feedback: Arc<Mutex<Vec<FeedbackRequest>>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockDynamoServer {
+    /// Start the server; every decision is answered after `response_delay`.
+    pub fn start(decision: MockDecision, response_delay: Duration) -> Result<Self> {
+        let feedback = Arc::new(Mutex::new(Vec::new()));
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let (addr_tx, addr_rx) = mpsc::channel();
+
+        let received = feedback.clone();
+        let thread = std::thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(error) => {
+                    let _ = addr_tx.send(Err(anyhow!(error)));
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let decide = warp::post()
+                    .and(warp::path("decide"))
+                    .and(warp::body::json())
+                    .then(move |request: DecisionRequest| {
+                        let decision = decision.clone();
+                        async move {
+                            tokio::time::sleep(response_delay).await;
+                            let connector = match decision {
+                                MockDecision::First => request.eligible_connectors.first().cloned(),
+                                MockDecision::Prefer(connector) => Some(connector),
+                                MockDecision::Error => None,
+                            };
+                            match connector {
+                                Some(connector) => warp::reply::with_status(
+                                    warp::reply::json(&DecisionResponse { connector }),
+                                    StatusCode::OK,
+                                ),
+                                None => warp::reply::with_status(
+                                    warp::reply::json(&()),
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                ),
+                            }
+                        }
+                    });
+                let feedback = warp::post()
+                    .and(warp::path("feedback"))
+                    .and(warp::body::json())
+                    .map(move |request: FeedbackRequest| {
+                        if let Ok(mut received) = received.lock() {
+                            received.push(request);
+                        }
+                        warp::reply()
+                    });
+
+                let bound = warp::serve(decide.or(feedback)).try_bind_with_graceful_shutdown(
+                    ([127, 0, 0, 1], 0),
+                    async {
+                        let _ = shutdown_rx.await;
+                    },
+                );
+                match bound {
+                    Ok((addr, server)) => {
+                        let _ = addr_tx.send(Ok(addr));
+                        server.await;
+                    }
+                    Err(error) => {
+                        let _ = addr_tx.send(Err(anyhow!(error)));
+                    }
+                }
+            });
+        });
+
+        let addr = addr_rx
+            .recv()
+            .context("Mock decision server thread exited")??;
+        Ok(MockDynamoServer {
+            addr,
+            feedback,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn decision_url(&self) -> String {
+        format!("http://{}/decide", self.addr)
+    }
+
+    pub fn feedback_url(&self) -> String {
+        format!("http://{}/feedback", self.addr)
+    }
+
+    /// Feedback received so far, oldest first.
+    pub fn received_feedback(&self) -> Vec<FeedbackRequest> {
+        self.feedback
+            .lock()
+            .map(|feedback| feedback.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockDynamoServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
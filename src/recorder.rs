@@ -1,131 +1,720 @@
-use crate::config::PaymentRecorderData;
-use crate::config::Key;
-use std::collections::HashMap;
-use anyhow::Result;
-use csv::Writer;
-use std::fs::OpenOptions;
+use crate::config::{sorted, PaymentRecorderData, Sample};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use csv::{ReaderBuilder, Writer};
 use crate::config::Metrics;
 use crate::config::Status;
+use crate::config::Classification;
+use crate::config::FailureCategory;
+use crate::stats::{rate_difference, two_proportion_p_value, wilson_interval};
 
 pub trait Recorder {
     fn record_transaction(
-        &self,
-        metrics: &mut Metrics,
+        &mut self,
+        data: &PaymentRecorderData,
     ) -> Result<()>;
+
+    /// Record a payment that no connector could serve, and why.
+    fn record_unroutable(&mut self, _sample: &Sample, _reason: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Persist anything buffered so far.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Payment method and payment method type of the recorded sample; empty when absent.
+fn payment_method_fields(data: &PaymentRecorderData) -> (&str, &str) {
+    sample_fields(&data.sample)
+}
+
+fn sample_fields(sample: &Sample) -> (&str, &str) {
+    let payment_method_type = sample.payment_method_type.as_ref().map_or("", |value| value.0.as_str());
+    (&sample.payment_method.0, payment_method_type)
 }
 
-impl Recorder for PaymentRecorderData {
+/// Open `path` for writing, keeping what is already in it when `append` is set.
+fn open<P: AsRef<Path>>(path: P, append: bool) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path.as_ref())
+        .with_context(|| format!("Failed to open output file: {:?}", path.as_ref()))
+}
+
+/// Line opening the records of a run in the records CSV.
+const SEED_PREFIX: &str = "# seed: ";
+
+/// Writes every transaction to the records CSV and, for labelled transactions,
+/// its classification to the classification CSV.
+pub struct CsvRecorder {
+    records: Writer<File>,
+    classification: Option<Writer<File>>,
+}
+
+impl CsvRecorder {
+    /// Start the records of a run with `seed`, replacing earlier runs in the files
+    /// unless `append` is set.
+    pub fn new<P: AsRef<Path>>(records_path: P, classification_path: Option<P>, seed: u64, append: bool) -> Result<Self> {
+        let mut records = open(records_path, append)?;
+        writeln!(records, "{}{}", SEED_PREFIX, seed).context("Failed to write records file")?;
+        Ok(CsvRecorder {
+            records: Writer::from_writer(records),
+            classification: classification_path
+                .map(|path| open(path, append).map(Writer::from_writer))
+                .transpose()?,
+        })
+    }
+}
+
+impl Recorder for CsvRecorder {
     fn record_transaction(
-        &self,
-        metrics: &mut Metrics,
+        &mut self,
+        data: &PaymentRecorderData,
     ) -> Result<()> {
-        // Open the CSV file in append mode
-        let data = self;
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(true)
-            .open("records.csv")
-            .unwrap();
-        let mut wtr = Writer::from_writer(file);
-
         // Write the transaction details to the CSV file
-        let payment_data: HashMap<&str, &str> = serde_json::from_str(&data.payment_data.0).unwrap();
-        let verdict_str = format!("{:?}", &self.verdict);
-        let mut record = vec![
-            &self.connector.0,
+        let (payment_method, payment_method_type) = payment_method_fields(data);
+        let verdict_str = format!("{:?}", &data.verdict);
+        let latency_str = data.latency.as_millis().to_string();
+        let failure_reason = data.failure_reason.as_ref().map_or("", |reason| reason.0.as_str());
+        let attempt_str = data.attempt.to_string();
+        let final_verdict_str = format!("{:?}", &data.final_verdict);
+        let timestamp_str = data.timestamp.as_millis().to_string();
+        let regret_str = format!("{:.4}", data.regret);
+        self.records.write_record([
+            data.connector.0.as_str(),
             &verdict_str,
-        ];
-
-        let mut payment_method_str = String::new();
-        if let Some(payment_method) = payment_data.get("payment_methods") {
-            payment_method_str = payment_method.to_string();
-            record.push(&payment_method_str);
-        }
-        let mut payment_method_type_str = String::new();
-        if let Some(payment_method_type) = payment_data.get("payment_method_type") {
-            payment_method_type_str = payment_method_type.to_string();
-            record.push(&payment_method_type_str);
-        }
-        wtr.write_record(&record)?;
-        wtr.flush()?;
-
-        // New: Update metrics data with the transaction
-        let pm = if payment_method_str.is_empty() { "N/A".to_string() } else { payment_method_str.clone() };
-        let pmt = if payment_method_type_str.is_empty() { "N/A".to_string() } else { payment_method_type_str.clone() };
-        let connector = self.connector.0.clone();
-        let verdict = self.verdict.clone();
-
-        // Assuming metrics.key is: HashMap<String, HashMap<String, HashMap<String, HashMap<Status, u32>>>>
-        metrics.key.entry(connector).or_insert_with(HashMap::new)
-            .entry(pm).or_insert_with(HashMap::new)
-            .entry(pmt).or_insert_with(HashMap::new)
-            .entry(verdict).and_modify(|e| *e += 1).or_insert(1);
+            payment_method,
+            payment_method_type,
+            &latency_str,
+            failure_reason,
+            &attempt_str,
+            &final_verdict_str,
+            &timestamp_str,
+            &regret_str,
+        ])?;
 
+        if let (Some(label), Some(wtr)) = (&data.label, self.classification.as_mut()) {
+            let classification = Classification::classify(label, &data.connector, &data.verdict);
+            wtr.serialize((&label.0, &data.connector.0, &verdict_str, classification))?;
+        }
+        Ok(())
+    }
+
+    fn record_unroutable(&mut self, sample: &Sample, reason: &str) -> Result<()> {
+        let (payment_method, payment_method_type) = sample_fields(sample);
+        let timestamp_str = sample.timestamp_ms.to_string();
+        self.records.write_record([
+            "",
+            "Unroutable",
+            payment_method,
+            payment_method_type,
+            "",
+            reason,
+            "0",
+            "Unroutable",
+            &timestamp_str,
+            "",
+        ])?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.records.flush()?;
+        if let Some(wtr) = self.classification.as_mut() {
+            wtr.flush()?;
+        }
         Ok(())
     }
 }
 
-pub fn print_metrics(metrics: &Metrics) {
-    // Print the success rate for each connector
-    let mut map: HashMap<String, usize> = HashMap::new();
-    let mut total_success_count = 0;
-    // println!("Success Rate Metrics: {:?}",metrics);
-    for (connector, payment_method_map) in &metrics.key {
-        let mut total_count = 0;
-        let mut success_count = 0;
-        for (_, payment_method_type_map) in payment_method_map {
-            for (_, status_map) in payment_method_type_map {
-                for (status, count) in status_map {
-                    total_count += count;
-                    if *status == Status::Success {
-                        success_count += count;
-                        total_success_count += count;
-                    }
-                    
-                }
+impl Metrics {
+    pub fn add(&mut self, connector: &str, payment_method: &str, payment_method_type: &str, verdict: Status) {
+        let or_na = |value: &str| if value.is_empty() { "N/A".to_string() } else { value.to_string() };
+        self.key.entry(connector.to_string()).or_default()
+            .entry(or_na(payment_method)).or_default()
+            .entry(or_na(payment_method_type)).or_default()
+            .entry(verdict).and_modify(|e| *e += 1).or_insert(1);
+    }
+
+    pub fn add_latency(&mut self, connector: &str, payment_method: &str, latency_ms: u64) {
+        let payment_method = if payment_method.is_empty() { "N/A" } else { payment_method };
+        self.latencies.entry(connector.to_string()).or_default()
+            .entry(payment_method.to_string()).or_default()
+            .push(latency_ms);
+    }
+
+    /// Count a failed or timed out transaction under its reason.
+    pub fn add_failure_reason(&mut self, connector: &str, payment_method: &str, verdict: &Status, failure_reason: &str) {
+        let reason = match (verdict, failure_reason) {
+            (Status::Success, _) => return,
+            (Status::Timeout, "") => "timeout",
+            (_, "") => "unspecified",
+            (_, reason) => reason,
+        };
+        let payment_method = if payment_method.is_empty() { "N/A" } else { payment_method };
+        self.failure_reasons.entry(connector.to_string()).or_default()
+            .entry(payment_method.to_string()).or_default()
+            .entry(reason.to_string()).and_modify(|e| *e += 1).or_insert(1);
+    }
+
+    /// Count an attempt; the first attempt of a payment also counts the payment.
+    pub fn add_attempt(&mut self, attempt: u32, verdict: &Status, final_verdict: &Status) {
+        self.payments.attempts += 1;
+        if attempt == 1 {
+            self.payments.total += 1;
+            if *verdict == Status::Success {
+                self.payments.first_attempt_success += 1;
+            }
+            if *final_verdict == Status::Success {
+                self.payments.final_success += 1;
             }
         }
-        // println!("Total transactions from {:?} connector: {}", connector, total_count);
-        map.insert(connector.clone(), total_count);
-        let success_rate = (success_count as f64 / total_count as f64) * 100.0;
-        println!("Connector: {:?}, Success Rate: {:.2}%", connector, success_rate);
+    }
 
-        // For each payment method
-        for (payment_method, payment_method_type_map) in payment_method_map {
-            let mut total_count = 0;
-            let mut success_count = 0;
-            for (_, status_map) in payment_method_type_map {
+    pub fn add_unroutable(&mut self, payment_method: &str, reason: &str) {
+        self.unroutable.entry(payment_method.to_string()).or_default()
+            .entry(reason.to_string()).and_modify(|e| *e += 1).or_insert(1);
+    }
+
+    pub fn add_regret(&mut self, regret: f64) {
+        let cumulative = self.regret.last().copied().unwrap_or(0.0) + regret;
+        self.regret.push(cumulative);
+    }
+
+    /// Expected successes lost against always routing to the best eligible connector.
+    pub fn cumulative_regret(&self) -> f64 {
+        self.regret.last().copied().unwrap_or(0.0)
+    }
+
+    /// Transactions and successes per connector.
+    pub fn connector_totals(&self) -> BTreeMap<String, (usize, usize)> {
+        let mut totals = BTreeMap::new();
+        for (connector, payment_method_map) in &self.key {
+            let (transactions, successes) = totals.entry(connector.clone()).or_insert((0, 0));
+            for status_map in payment_method_map.values().flat_map(|types| types.values()) {
                 for (status, count) in status_map {
-                    total_count += count;
+                    *transactions += count;
                     if *status == Status::Success {
-                        success_count += count;
+                        *successes += count;
                     }
                 }
             }
-            let success_rate = (success_count as f64 / total_count as f64) * 100.0;
-            println!("Connector: {:?}, Payment Method: {:?}, Success Rate: {:.2}%", connector, payment_method, success_rate);
+        }
+        totals
+    }
+
+    /// Transactions and successes over all connectors.
+    pub fn totals(&self) -> (usize, usize) {
+        self.connector_totals()
+            .values()
+            .fold((0, 0), |(transactions, successes), (t, s)| (transactions + t, successes + s))
+    }
+
+    /// Count a transaction in the time bucket of `timestamp`, if buckets are kept.
+    pub fn add_to_timeline(&mut self, connector: &str, verdict: &Status, timestamp: Duration) {
+        let Some(bucket) = self.bucket.filter(|bucket| !bucket.is_zero()) else {
+            return;
+        };
+        let index = (timestamp.as_millis() / bucket.as_millis()) as u64;
+        let (transactions, successes) = self.timeline.entry(index).or_default()
+            .entry(connector.to_string()).or_default();
+        *transactions += 1;
+        if *verdict == Status::Success {
+            *successes += 1;
+        }
+    }
+
+    pub fn add_classification(&mut self, label: &str, classification: Classification) {
+        self.classification.entry(label.to_string()).or_default()
+            .entry(classification).and_modify(|e| *e += 1).or_insert(1);
+    }
+}
+
+impl Recorder for Metrics {
+    fn record_transaction(
+        &mut self,
+        data: &PaymentRecorderData,
+    ) -> Result<()> {
+        let (payment_method, payment_method_type) = payment_method_fields(data);
+        self.add(&data.connector.0, payment_method, payment_method_type, data.verdict.clone());
+        self.add_latency(&data.connector.0, payment_method, data.latency.as_millis() as u64);
+        let failure_reason = data.failure_reason.as_ref().map_or("", |reason| reason.0.as_str());
+        self.add_failure_reason(&data.connector.0, payment_method, &data.verdict, failure_reason);
+        self.add_attempt(data.attempt, &data.verdict, &data.final_verdict);
+        self.add_to_timeline(&data.connector.0, &data.verdict, data.timestamp);
+        self.add_regret(data.regret);
+        if let Some(label) = &data.label {
+            self.add_classification(&label.0, Classification::classify(label, &data.connector, &data.verdict));
+        }
+        Ok(())
+    }
+
+    fn record_unroutable(&mut self, sample: &Sample, reason: &str) -> Result<()> {
+        self.add_unroutable(&sample.payment_method.0, reason);
+        Ok(())
+    }
+}
+
+fn parse_status(status: &str) -> Result<Status> {
+    match status {
+        "Success" => Ok(Status::Success),
+        "Failure" => Ok(Status::Failure),
+        "Timeout" => Ok(Status::Timeout),
+        other => anyhow::bail!("Unknown status {:?} in records file", other),
+    }
+}
+
+/// Rebuild `Metrics` from the CSV files written by `CsvRecorder`, with a timeline
+/// of `bucket` wide time buckets if given.
+pub fn load_metrics<P: AsRef<Path>>(
+    records_path: P,
+    classification_path: Option<P>,
+    bucket: Option<Duration>,
+) -> Result<Metrics> {
+    let mut metrics = Metrics::with_buckets(bucket);
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(records_path.as_ref())
+        .with_context(|| format!("Failed to read records file: {:?}", records_path.as_ref()))?;
+    for record in reader.records() {
+        let record = record?;
+        let field = |index| record.get(index).unwrap_or_default();
+        if let Some(seed) = field(0).strip_prefix(SEED_PREFIX) {
+            metrics.seeds.push(seed.parse().with_context(|| format!("Invalid seed {:?} in records file", seed))?);
+            continue;
+        }
+        if field(1) == "Unroutable" {
+            metrics.add_unroutable(field(2), field(5));
+            continue;
+        }
+        let verdict = parse_status(field(1))?;
+        // Records written before retries have no attempt and final status columns.
+        let attempt = if field(6).is_empty() {
+            1
+        } else {
+            field(6).parse().with_context(|| format!("Invalid attempt {:?} in records file", field(6)))?
+        };
+        let final_verdict = if field(7).is_empty() { verdict.clone() } else { parse_status(field(7))? };
+        metrics.add_attempt(attempt, &verdict, &final_verdict);
+        // Records written before the simulated clock have no timestamp column.
+        if !field(8).is_empty() {
+            let timestamp_ms = field(8).parse().with_context(|| format!("Invalid timestamp {:?} in records file", field(8)))?;
+            metrics.add_to_timeline(field(0), &verdict, Duration::from_millis(timestamp_ms));
+        }
+        // Records written before the regret metric have no regret column.
+        if !field(9).is_empty() {
+            let regret = field(9).parse().with_context(|| format!("Invalid regret {:?} in records file", field(9)))?;
+            metrics.add_regret(regret);
+        }
+        metrics.add_failure_reason(field(0), field(2), &verdict, field(5));
+        metrics.add(field(0), field(2), field(3), verdict);
+        // Records written before latency simulation have no latency column.
+        if !field(4).is_empty() {
+            let latency_ms = field(4).parse().with_context(|| format!("Invalid latency {:?} in records file", field(4)))?;
+            metrics.add_latency(field(0), field(2), latency_ms);
+        }
+    }
+
+    if let Some(path) = classification_path {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(path.as_ref())
+            .with_context(|| format!("Failed to read classification file: {:?}", path.as_ref()))?;
+        for record in reader.deserialize() {
+            let (label, _, _, classification): (String, String, String, Classification) = record?;
+            metrics.add_classification(&label, classification);
+        }
+    }
+    Ok(metrics)
+}
+
+/// Transactions, successes and timeouts counted in `status_maps`.
+fn tally<'a>(status_maps: impl IntoIterator<Item = &'a HashMap<Status, usize>>) -> (usize, usize, usize) {
+    let mut totals = (0, 0, 0);
+    for status_map in status_maps {
+        for (status, count) in status_map {
+            totals.0 += count;
+            match status {
+                Status::Success => totals.1 += count,
+                Status::Timeout => totals.2 += count,
+                Status::Failure => {}
+            }
+        }
+    }
+    totals
+}
+
+/// Success rate with its count and 95% Wilson interval, e.g.
+/// "38.46% (5/13, 95% CI: 17.71%-64.48%, below 30 transactions)".
+fn rate_summary(successes: usize, transactions: usize, min_transactions: usize) -> String {
+    let Some((low, high)) = wilson_interval(successes, transactions) else {
+        return "N/A (0 transactions)".to_string();
+    };
+    let below_minimum = if transactions < min_transactions {
+        format!(", below {} transactions", min_transactions)
+    } else {
+        String::new()
+    };
+    format!(
+        "{:.2}% ({}/{}, 95% CI: {:.2}%-{:.2}%{})",
+        successes as f64 / transactions as f64 * 100.0,
+        successes,
+        transactions,
+        low * 100.0,
+        high * 100.0,
+        below_minimum
+    )
+}
+
+/// Share of `count` in `total` with its counts, e.g. "25.10% (251/1000)".
+fn share(count: usize, total: usize) -> String {
+    if total == 0 {
+        return "N/A (0 transactions)".to_string();
+    }
+    format!("{:.2}% ({}/{})", count as f64 / total as f64 * 100.0, count, total)
+}
+
+/// Success rates per connector, payment method and payment method type, with counts
+/// and confidence intervals; rates over fewer than `min_transactions` are flagged.
+pub fn print_metrics(metrics: &Metrics, min_transactions: usize) {
+    let (total_transactions, total_success_count, total_timeout_count) =
+        tally(metrics.key.values().flat_map(|pm| pm.values()).flat_map(|pmt| pmt.values()));
+    if total_transactions == 0 {
+        println!("No transactions recorded.");
+        print_unroutable(metrics);
+        return;
+    }
+    for (connector, payment_method_map) in sorted(&metrics.key) {
+        let (total_count, success_count, timeout_count) =
+            tally(payment_method_map.values().flat_map(|pmt| pmt.values()));
+        println!("Connector: {:?}, Success Rate: {}", connector, rate_summary(success_count, total_count, min_transactions));
+        if timeout_count > 0 {
+            let timeout_rate = (timeout_count as f64 / total_count as f64) * 100.0;
+            println!("Connector: {:?}, Timeouts: {}, Timeout Rate: {:.2}%", connector, timeout_count, timeout_rate);
+        }
+
+        // For each payment method
+        for (payment_method, payment_method_type_map) in sorted(payment_method_map) {
+            let (total_count, success_count, _) = tally(payment_method_type_map.values());
+            println!(
+                "Connector: {:?}, Payment Method: {:?}, Success Rate: {}",
+                connector,
+                payment_method,
+                rate_summary(success_count, total_count, min_transactions)
+            );
 
             // For each payment method type
-            for (payment_method_type, status_map) in payment_method_type_map {
-                let mut total_count = 0;
-                let mut success_count = 0;
-                for (status, count) in status_map {
-                    total_count += count;
-                    if *status == Status::Success {
-                        success_count += count;
-                    }
-                }
-                let success_rate = (success_count as f64 / total_count as f64) * 100.0;
-                println!("Connector: {:?}, Payment Method: {:?}, Payment Method Type: {:?}, Success Rate: {:.2}%", connector, payment_method, payment_method_type, success_rate);
+            for (payment_method_type, status_map) in sorted(payment_method_type_map) {
+                let (total_count, success_count, _) = tally([status_map]);
+                println!(
+                    "Connector: {:?}, Payment Method: {:?}, Payment Method Type: {:?}, Success Rate: {}",
+                    connector,
+                    payment_method,
+                    payment_method_type,
+                    rate_summary(success_count, total_count, min_transactions)
+                );
             }
         }
     }
-    let total_transactions: usize = map.values().sum();
     println!("Total transactions: {}", total_transactions);
-    for (connector, total_count) in map {
+    for (connector, (total_count, _)) in metrics.connector_totals() {
         println!("Total transactions from {:?} connector: {:.2}%", connector, (total_count as f64 / total_transactions as f64) * 100.0);
     }
-    let total_success_rate = (total_success_count as f64 / total_transactions as f64) * 100.0;
-    println!("Total Success Rate: {:.2}%", total_success_rate);
+    println!("Total Success Rate: {}", rate_summary(total_success_count, total_transactions, min_transactions));
+    if total_timeout_count > 0 {
+        let total_timeout_rate = (total_timeout_count as f64 / total_transactions as f64) * 100.0;
+        println!("Total Timeouts: {}, Timeout Rate: {:.2}%", total_timeout_count, total_timeout_rate);
+    }
+    let payments = &metrics.payments;
+    if payments.attempts > payments.total {
+        println!(
+            "Total payments: {}, First Attempt Success Rate: {}, Final Success Rate: {}, Attempts per payment: {:.2}",
+            payments.total,
+            rate_summary(payments.first_attempt_success, payments.total, min_transactions),
+            rate_summary(payments.final_success, payments.total, min_transactions),
+            payments.attempts as f64 / payments.total as f64
+        );
+    }
+    print_unroutable(metrics);
+    print_connector_significance(metrics, min_transactions);
+}
+
+/// Payments no connector could serve, per payment method and reason.
+fn print_unroutable(metrics: &Metrics) {
+    let unroutable: usize = metrics.unroutable.values().flat_map(|reasons| reasons.values()).sum();
+    if unroutable == 0 {
+        return;
+    }
+    let payments = metrics.payments.total + unroutable;
+    println!(
+        "Unroutable payments: {}, {:.2}% of payments",
+        unroutable,
+        (unroutable as f64 / payments as f64) * 100.0
+    );
+    for (payment_method, reasons) in sorted(&metrics.unroutable) {
+        for (reason, count) in sorted(reasons) {
+            println!("Unroutable: Payment Method: {:?}, Reason: {:?}, Count: {}", payment_method, reason, count);
+        }
+    }
+}
+
+/// For every payment method, test whether the connector with the best success rate
+/// is significantly better than each other one with at least `min_transactions`.
+fn print_connector_significance(metrics: &Metrics, min_transactions: usize) {
+    // payment method -> connector, (successes, transactions)
+    type Counts<'a> = Vec<(&'a str, (usize, usize))>;
+    let mut by_payment_method: BTreeMap<&str, Counts> = BTreeMap::new();
+    for (connector, payment_method_map) in sorted(&metrics.key) {
+        for (payment_method, payment_method_type_map) in payment_method_map {
+            let (transactions, successes, _) = tally(payment_method_type_map.values());
+            if transactions >= min_transactions {
+                by_payment_method.entry(payment_method).or_default().push((connector, (successes, transactions)));
+            }
+        }
+    }
+    let rate = |(successes, transactions): (usize, usize)| successes as f64 / transactions as f64;
+    let mut header_printed = false;
+    for (payment_method, connectors) in by_payment_method {
+        let Some(&(best, best_counts)) = connectors.iter().max_by(|(_, a), (_, b)| rate(*a).total_cmp(&rate(*b))) else {
+            continue;
+        };
+        for &(connector, counts) in connectors.iter().filter(|(connector, _)| *connector != best) {
+            let Some(p_value) = two_proportion_p_value(counts, best_counts) else {
+                continue;
+            };
+            if !header_printed {
+                println!("Best connector per payment method against the others (two-proportion z-test, 5% level):");
+                header_printed = true;
+            }
+            println!(
+                "Payment Method: {:?}, {:?} vs {:?}: Difference: {:+.2}pp, p-value: {:.4} ({})",
+                payment_method,
+                best,
+                connector,
+                (rate(best_counts) - rate(counts)) * 100.0,
+                p_value,
+                if p_value < 0.05 { "significant" } else { "not significant" }
+            );
+        }
+    }
+}
+
+pub fn print_classification_metrics(metrics: &Metrics) {
+    if metrics.classification.is_empty() {
+        return;
+    }
+    println!("Classification against the best connector (label) per transaction:");
+    for (label, classification_map) in sorted(&metrics.classification) {
+        let count = |classification| classification_map.get(&classification).copied().unwrap_or(0);
+        let true_positive = count(Classification::TruePositive);
+        let false_negative = count(Classification::FalseNegative);
+        let false_positive = count(Classification::FalsePositive);
+        let true_negative = count(Classification::TrueNegative);
+        let ratio = |numerator: usize, denominator: usize| {
+            if denominator == 0 {
+                "N/A".to_string()
+            } else {
+                format!("{:.2}%", numerator as f64 / denominator as f64 * 100.0)
+            }
+        };
+        println!(
+            "Label: {:?}, TP: {}, FN: {}, FP: {}, TN: {}, Precision: {}, Recall: {}",
+            label,
+            true_positive,
+            false_negative,
+            false_positive,
+            true_negative,
+            ratio(true_positive, true_positive + false_positive),
+            ratio(true_positive, true_positive + false_negative),
+        );
+    }
+}
+
+/// Nearest-rank percentile of already sorted values.
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn latency_summary(latencies: &[u64]) -> String {
+    let mut sorted = latencies.to_vec();
+    sorted.sort_unstable();
+    format!(
+        "p50: {}ms, p90: {}ms, p99: {}ms",
+        percentile(&sorted, 50),
+        percentile(&sorted, 90),
+        percentile(&sorted, 99)
+    )
+}
+
+pub fn print_latency_metrics(metrics: &Metrics) {
+    for (connector, payment_method_map) in sorted(&metrics.latencies) {
+        let all: Vec<u64> = payment_method_map.values().flatten().copied().collect();
+        println!("Connector: {:?}, Latency {}", connector, latency_summary(&all));
+        for (payment_method, latencies) in sorted(payment_method_map) {
+            println!(
+                "Connector: {:?}, Payment Method: {:?}, Latency {}",
+                connector,
+                payment_method,
+                latency_summary(latencies)
+            );
+        }
+    }
+}
+
+pub fn print_failure_metrics(metrics: &Metrics) {
+    for (connector, payment_method_map) in sorted(&metrics.failure_reasons) {
+        let mut by_category: HashMap<FailureCategory, usize> = HashMap::new();
+        for reason_map in payment_method_map.values() {
+            for (reason, count) in reason_map {
+                *by_category.entry(FailureCategory::of(reason)).or_default() += count;
+            }
+        }
+        let count = |category| by_category.get(&category).copied().unwrap_or(0);
+        let (user, psp, unknown) = (
+            count(FailureCategory::User),
+            count(FailureCategory::Psp),
+            count(FailureCategory::Unknown),
+        );
+        let total = (user + psp + unknown) as f64;
+        println!(
+            "Connector: {:?}, User Declines: {} ({:.2}% of failures), PSP Failures: {} ({:.2}% of failures), Unspecified: {}",
+            connector,
+            user,
+            user as f64 / total * 100.0,
+            psp,
+            psp as f64 / total * 100.0,
+            unknown
+        );
+        for (payment_method, reason_map) in sorted(payment_method_map) {
+            for (reason, count) in sorted(reason_map) {
+                println!(
+                    "Connector: {:?}, Payment Method: {:?}, Failure Reason: {:?} ({:?}), Count: {}",
+                    connector,
+                    payment_method,
+                    reason,
+                    FailureCategory::of(reason),
+                    count
+                );
+            }
+        }
+    }
+}
+
+/// Transactions, success rate and connector shares per time bucket, if buckets were
+/// kept; rates over fewer than `min_transactions` are flagged.
+pub fn print_timeline_metrics(metrics: &Metrics, min_transactions: usize) {
+    let Some(bucket) = metrics.bucket.filter(|_| !metrics.timeline.is_empty()) else {
+        return;
+    };
+    println!("Transactions per {}s of simulated time:", bucket.as_secs_f64());
+    for (index, connectors) in &metrics.timeline {
+        let start = bucket * *index as u32;
+        let transactions: usize = connectors.values().map(|(transactions, _)| transactions).sum();
+        let successes: usize = connectors.values().map(|(_, successes)| successes).sum();
+        println!(
+            "Time: {}s-{}s, Transactions: {}, Success Rate: {}",
+            start.as_secs(),
+            (start + bucket).as_secs(),
+            transactions,
+            rate_summary(successes, transactions, min_transactions)
+        );
+        for (connector, (connector_transactions, connector_successes)) in sorted(connectors) {
+            println!(
+                "  Connector: {:?}, Share: {}, Success Rate: {}",
+                connector,
+                share(*connector_transactions, transactions),
+                rate_summary(*connector_successes, *connector_transactions, min_transactions)
+            );
+        }
+    }
+}
+
+/// Side-by-side success rate, latency and connector share of routers run on the same
+/// samples, and each router's success rate difference to the first one.
+pub fn print_comparison(runs: &[(String, &Metrics)]) {
+    let mut connectors: Vec<String> = Vec::new();
+    for (router, metrics) in runs {
+        let (transactions, successes) = metrics.totals();
+        let latencies: Vec<u64> = metrics.latencies.values().flat_map(|map| map.values().flatten()).copied().collect();
+        println!(
+            "Router: {:?}, Success Rate: {}, Latency {}, Cumulative Regret: {:.2}",
+            router,
+            rate_summary(successes, transactions, 0),
+            latency_summary(&latencies),
+            metrics.cumulative_regret()
+        );
+        connectors.extend(metrics.connector_totals().into_keys());
+    }
+    connectors.sort();
+    connectors.dedup();
+
+    println!("Connector share per router:");
+    for connector in &connectors {
+        let shares: Vec<String> = runs
+            .iter()
+            .map(|(router, metrics)| {
+                let (transactions, _) = metrics.totals();
+                let (connector_transactions, _) = metrics.connector_totals().get(connector).copied().unwrap_or((0, 0));
+                format!("{:?}: {}", router, share(connector_transactions, transactions))
+            })
+            .collect();
+        println!("Connector: {:?}, {}", connector, shares.join(", "));
+    }
+
+    let Some(((baseline, baseline_metrics), others)) = runs.split_first() else {
+        return;
+    };
+    let (baseline_transactions, baseline_successes) = baseline_metrics.totals();
+    println!("Success Rate difference to {:?} (95% CI):", baseline);
+    for (router, metrics) in others {
+        let (transactions, successes) = metrics.totals();
+        let baseline_counts = (baseline_successes, baseline_transactions);
+        match rate_difference(baseline_counts, (successes, transactions))
+            .zip(two_proportion_p_value(baseline_counts, (successes, transactions)))
+        {
+            Some(((difference, half_width), p_value)) => println!(
+                "Router: {:?}, Difference: {:+.2}pp, CI: [{:+.2}pp, {:+.2}pp], p-value: {:.4} ({})",
+                router,
+                difference * 100.0,
+                (difference - half_width) * 100.0,
+                (difference + half_width) * 100.0,
+                p_value,
+                if p_value < 0.05 { "significant" } else { "not significant" }
+            ),
+            None => println!("Router: {:?}, Difference: N/A", router),
+        }
+    }
+}
+
+/// Cumulative regret at every tenth of the recorded transactions.
+pub fn print_regret_metrics(metrics: &Metrics) {
+    let transactions = metrics.regret.len();
+    if transactions == 0 {
+        return;
+    }
+    println!("Regret (expected successes lost against the best eligible connector):");
+    for tenth in 1..=10 {
+        let count = (transactions * tenth).div_ceil(10);
+        let regret = metrics.regret[count - 1];
+        println!(
+            "Transactions: {}, Cumulative Regret: {:.2}, Regret per 100 transactions: {:.2}",
+            count,
+            regret,
+            regret / count as f64 * 100.0
+        );
+    }
 }
@@ -0,0 +1,43 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Random number generator used throughout a simulation.
+pub type SimRng = ChaCha8Rng;
+
+/// Independent random streams derived from one seed, so that e.g. a router drawing
+/// more numbers doesn't change the samples or PSP outcomes of the run.
+#[derive(Debug, Clone, Copy)]
+pub enum Stream {
+    Sampler = 0,
+    Router = 1,
+    Evaluator = 2,
+    Incidents = 3,
+    Arrivals = 4,
+}
+
+pub fn stream(seed: u64, stream: Stream) -> SimRng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(stream as u64);
+    rng
+}
+
+/// Seed for runs that don't specify one; print it so the run can be replayed.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// Stream deciding the incident of one interval of an incident schedule, so that any
+/// interval can be looked up without replaying the ones before it.
+pub fn incident_stream(seed: u64, schedule: &str, interval: u64) -> SimRng {
+    // FNV-1a keeps the schedule's part of the seed stable across Rust versions.
+    let schedule_hash = schedule
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    let mut key = [0u8; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..16].copy_from_slice(&interval.to_le_bytes());
+    key[16..24].copy_from_slice(&schedule_hash.to_le_bytes());
+    let mut rng = ChaCha8Rng::from_seed(key);
+    rng.set_stream(Stream::Incidents as u64);
+    rng
+}
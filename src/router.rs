@@ -0,0 +1,305 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::Duration;
+use anyhow::{ensure, Result};
+use rand::Rng;
+use crate::config::{Feedback, FeedbackDelay, Key, MerchantConfig, PspSimulationConfig, RoutingConfig, Sample, SimTime};
+use crate::rng::SimRng;
+
+mod bandit;
+pub mod dynamo;
+mod success_rate;
+
+pub use bandit::{EpsilonGreedyRouting, ThompsonSamplingRouting, Ucb1Routing};
+pub use dynamo::DynamoRouting;
+pub use success_rate::SuccessRateRouting;
+
+pub trait Router {
+    /// Name of the algorithm, as used in the `routing.algorithm` config field.
+    fn name(&self) -> &str;
+
+    /// Pick one connector out of the `connectors` that are eligible for `sample`.
+    fn get_connector(
+        &mut self,
+        sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key>;
+
+    /// Receive the outcome of a transaction previously routed by this router.
+    fn feedback(&mut self, _feedback: &Feedback) -> Result<()> {
+        Ok(())
+    }
+
+    /// Lines worth reporting once the run is over, e.g. how often a service failed.
+    fn summary(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// What the oracle needs to know the true success rates: the PSP config, the
+/// merchant's timeouts and the point of the run the decision being made is evaluated
+/// at, which the simulation updates before every decision.
+#[derive(Clone)]
+pub struct GroundTruth {
+    pub psp: Rc<PspSimulationConfig>,
+    pub merchant: Rc<MerchantConfig>,
+    pub at: Rc<Cell<SimTime>>,
+}
+
+impl RoutingConfig {
+    /// Build the router; all of its random decisions are drawn from `rng`, and only
+    /// the oracle looks at `truth`.
+    pub fn build(&self, rng: SimRng, truth: &GroundTruth) -> Result<Box<dyn Router>> {
+        let router: Box<dyn Router> = match self {
+            RoutingConfig::StraightThrough => Box::new(StraightThroughRouting::new(rng)),
+            RoutingConfig::SuccessRate { window, exploration_percentage } => {
+                Box::new(SuccessRateRouting::new(*window, *exploration_percentage, rng))
+            }
+            RoutingConfig::EpsilonGreedy { epsilon, decay, min_epsilon } => {
+                Box::new(EpsilonGreedyRouting::new(*epsilon, *decay, *min_epsilon, rng))
+            }
+            RoutingConfig::Ucb1 { exploration_factor } => {
+                Box::new(Ucb1Routing::new(*exploration_factor))
+            }
+            RoutingConfig::ThompsonSampling { prior_alpha, prior_beta } => {
+                Box::new(ThompsonSamplingRouting::new(*prior_alpha, *prior_beta, rng)?)
+            }
+            RoutingConfig::Oracle => Box::new(OracleRouting::new(truth.clone())),
+            RoutingConfig::Dynamo { decision_url, feedback_url, timeout_ms, fallback } => {
+                Box::new(DynamoRouting::new(
+                    decision_url.clone(),
+                    feedback_url.clone(),
+                    Duration::from_millis(*timeout_ms),
+                    fallback.build(rng, truth)?,
+                )?)
+            }
+        };
+        Ok(router)
+    }
+}
+
+/// Picks uniformly at random among the eligible connectors and ignores feedback.
+pub struct StraightThroughRouting {
+    rng: SimRng,
+}
+
+impl StraightThroughRouting {
+    pub fn new(rng: SimRng) -> Self {
+        StraightThroughRouting { rng }
+    }
+}
+
+impl Router for StraightThroughRouting {
+    fn name(&self) -> &str {
+        "straight_through"
+    }
+
+    fn get_connector(
+        &mut self,
+        _sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key> {
+        ensure!(!connectors.is_empty(), "No connectors to route to");
+        Ok(connectors[self.rng.gen_range(0..connectors.len())].clone())
+    }
+}
+
+/// Picks the connector with the highest true expected success, read from the PSP
+/// config at the point of the run the decision is evaluated at and discounted by the
+/// chance of a timeout; the first one wins ties.
+pub struct OracleRouting {
+    truth: GroundTruth,
+}
+
+impl OracleRouting {
+    pub fn new(truth: GroundTruth) -> Self {
+        OracleRouting { truth }
+    }
+}
+
+impl Router for OracleRouting {
+    fn name(&self) -> &str {
+        "oracle"
+    }
+
+    fn get_connector(
+        &mut self,
+        sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key> {
+        ensure!(!connectors.is_empty(), "No connectors to route to");
+        let best = self.truth.psp.best_connector(connectors, sample, self.truth.at.get(), &self.truth.merchant);
+        Ok(best.unwrap_or_else(|| connectors[0].clone()))
+    }
+}
+
+/// Holds feedback back from the router until the configured `FeedbackDelay` has elapsed,
+/// mimicking the lag of real payment webhooks.
+pub struct FeedbackQueue {
+    delay: FeedbackDelay,
+    /// Feedback by the transaction and time it is due at, then by push order. Only
+    /// the part the delay is measured in is kept, so the first entry is always the
+    /// next one to become due.
+    pending: BTreeMap<(u64, Duration, u64), Feedback>,
+    pushed: u64,
+}
+
+impl FeedbackQueue {
+    pub fn new(delay: FeedbackDelay) -> Self {
+        FeedbackQueue {
+            delay,
+            pending: BTreeMap::new(),
+            pushed: 0,
+        }
+    }
+
+    /// Queue the feedback of the `transaction`-th transaction, whose PSP response arrived
+    /// at `now`, and return the time it becomes due.
+    pub fn push(&mut self, feedback: Feedback, transaction: u64, now: Duration) -> Duration {
+        let (due_transaction, due_time) = match self.delay {
+            FeedbackDelay::Immediate => (transaction, now),
+            // `deliver` runs before routing its transaction, so transactions
+            // `transaction + 1 ..= transaction + count` are routed without the feedback.
+            FeedbackDelay::AfterTransactions(count) => (transaction + count + 1, now),
+            FeedbackDelay::AfterMs(ms) => (transaction, now + Duration::from_millis(ms)),
+        };
+        // The other part is always reached by the time the feedback is delivered.
+        let due = match self.delay {
+            FeedbackDelay::Immediate => (0, Duration::ZERO),
+            FeedbackDelay::AfterTransactions(_) => (due_transaction, Duration::ZERO),
+            FeedbackDelay::AfterMs(_) => (0, due_time),
+        };
+        self.pending.insert((due.0, due.1, self.pushed), feedback);
+        self.pushed += 1;
+        due_time
+    }
+
+    /// Hand every feedback that is due at `transaction` / `now` to the router, in the
+    /// order the PSP responses arrived.
+    pub fn deliver(&mut self, router: &mut dyn Router, transaction: u64, now: Duration) -> Result<()> {
+        let mut due = Vec::new();
+        while let Some(entry) = self.pending.first_entry() {
+            let (due_transaction, due_time, pushed) = *entry.key();
+            if due_transaction > transaction || due_time > now {
+                break;
+            }
+            due.push((pushed, entry.remove()));
+        }
+        due.sort_by_key(|(pushed, feedback)| (feedback.timestamp, *pushed));
+        for (_, feedback) in due {
+            router.feedback(&feedback)?;
+        }
+        Ok(())
+    }
+
+    /// Hand all remaining feedback to the router, regardless of delay.
+    pub fn flush(&mut self, router: &mut dyn Router) -> Result<()> {
+        let mut pending: Vec<_> = std::mem::take(&mut self.pending).into_iter().collect();
+        pending.sort_by_key(|((_, _, pushed), _)| *pushed);
+        for (_, feedback) in pending {
+            router.feedback(&feedback)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Status;
+
+    /// Remembers the transactions whose feedback it received, by sample amount.
+    #[derive(Default)]
+    struct Recording(Vec<u64>);
+
+    impl Router for Recording {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn get_connector(&mut self, _sample: &Sample, connectors: &[Key]) -> Result<Key> {
+            Ok(connectors[0].clone())
+        }
+
+        fn feedback(&mut self, feedback: &Feedback) -> Result<()> {
+            self.0.push(feedback.sample.amount);
+            Ok(())
+        }
+    }
+
+    fn feedback(transaction: u64, timestamp: Duration) -> Feedback {
+        Feedback {
+            connector: Key("stripe".to_string()),
+            sample: Sample {
+                amount: transaction,
+                currency: "USD".to_string(),
+                payment_method: Key("card".to_string()),
+                payment_method_type: None,
+                attributes: Default::default(),
+                timestamp_ms: 0,
+            },
+            status: Status::Success,
+            latency: Duration::ZERO,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn immediate_feedback_is_delivered_before_the_next_transaction() {
+        let mut queue = FeedbackQueue::new(FeedbackDelay::Immediate);
+        let mut router = Recording::default();
+        queue.push(feedback(0, Duration::ZERO), 0, Duration::ZERO);
+        queue.deliver(&mut router, 1, Duration::ZERO).unwrap();
+        assert_eq!(router.0, vec![0]);
+    }
+
+    #[test]
+    fn feedback_after_one_transaction_skips_exactly_one() {
+        let mut queue = FeedbackQueue::new(FeedbackDelay::AfterTransactions(1));
+        let mut router = Recording::default();
+        queue.push(feedback(0, Duration::ZERO), 0, Duration::ZERO);
+        queue.deliver(&mut router, 1, Duration::ZERO).unwrap();
+        assert!(router.0.is_empty());
+        queue.deliver(&mut router, 2, Duration::ZERO).unwrap();
+        assert_eq!(router.0, vec![0]);
+    }
+
+    #[test]
+    fn feedback_after_ms_waits_for_simulated_time() {
+        let mut queue = FeedbackQueue::new(FeedbackDelay::AfterMs(100));
+        let mut router = Recording::default();
+        let now = Duration::from_millis(50);
+        assert_eq!(queue.push(feedback(0, now), 0, now), Duration::from_millis(150));
+        queue.deliver(&mut router, 5, Duration::from_millis(149)).unwrap();
+        assert!(router.0.is_empty());
+        queue.deliver(&mut router, 5, Duration::from_millis(150)).unwrap();
+        assert_eq!(router.0, vec![0]);
+    }
+
+    #[test]
+    fn feedback_due_earlier_is_not_held_back_by_feedback_pushed_before_it() {
+        let mut queue = FeedbackQueue::new(FeedbackDelay::AfterTransactions(2));
+        let mut router = Recording::default();
+        // Payment 5 is answered before the older payment 3, which is due first.
+        queue.push(feedback(5, Duration::from_millis(10)), 5, Duration::from_millis(10));
+        queue.push(feedback(3, Duration::from_millis(20)), 3, Duration::from_millis(20));
+        queue.deliver(&mut router, 6, Duration::from_millis(30)).unwrap();
+        assert_eq!(router.0, vec![3]);
+        queue.deliver(&mut router, 8, Duration::from_millis(40)).unwrap();
+        assert_eq!(router.0, vec![3, 5]);
+    }
+
+    #[test]
+    fn feedback_due_together_is_delivered_in_response_order() {
+        let mut queue = FeedbackQueue::new(FeedbackDelay::AfterMs(100));
+        let mut router = Recording::default();
+        queue.push(feedback(0, Duration::from_millis(10)), 0, Duration::from_millis(10));
+        queue.push(feedback(1, Duration::from_millis(20)), 1, Duration::from_millis(20));
+        queue.push(feedback(2, Duration::from_millis(30)), 2, Duration::from_millis(30));
+        queue.deliver(&mut router, 3, Duration::from_millis(125)).unwrap();
+        assert_eq!(router.0, vec![0, 1]);
+        queue.flush(&mut router).unwrap();
+        assert_eq!(router.0, vec![0, 1, 2]);
+    }
+}
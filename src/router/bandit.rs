@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use anyhow::{ensure, Result};
+use rand::Rng;
+use rand_distr::{Beta, Distribution};
+use crate::config::{Feedback, Key, Sample, Status};
+use crate::router::Router;
+use crate::rng::SimRng;
+
+/// (payment method, payment method type) of a sample; each context has its own arms.
+type Context = (String, String);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Arm {
+    successes: u64,
+    pulls: u64,
+}
+
+impl Arm {
+    fn mean(&self) -> f64 {
+        if self.pulls == 0 {
+            0.0
+        } else {
+            self.successes as f64 / self.pulls as f64
+        }
+    }
+}
+
+/// Success/pull counts per connector, keyed by the sample's payment method dimensions.
+#[derive(Debug, Default)]
+struct Arms(HashMap<Context, HashMap<Key, Arm>>);
+
+impl Arms {
+    fn get(&self, context: &Context, connector: &Key) -> Arm {
+        self.0
+            .get(context)
+            .and_then(|arms| arms.get(connector))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn total_pulls(&self, context: &Context) -> u64 {
+        self.0
+            .get(context)
+            .map(|arms| arms.values().map(|arm| arm.pulls).sum())
+            .unwrap_or(0)
+    }
+
+    fn update(&mut self, feedback: &Feedback) {
+        let arm = self
+            .0
+            .entry(feedback.sample.payment_method_dimensions())
+            .or_default()
+            .entry(feedback.connector.clone())
+            .or_default();
+        arm.pulls += 1;
+        if feedback.status == Status::Success {
+            arm.successes += 1;
+        }
+    }
+
+    /// Eligible connector with the highest `score` in `context`; the first one wins ties.
+    fn best_by(
+        &self,
+        context: &Context,
+        connectors: &[Key],
+        mut score: impl FnMut(Arm) -> f64,
+    ) -> Key {
+        let mut best = &connectors[0];
+        let mut best_score = f64::NEG_INFINITY;
+        for connector in connectors {
+            let value = score(self.get(context, connector));
+            if value > best_score {
+                best = connector;
+                best_score = value;
+            }
+        }
+        best.clone()
+    }
+}
+
+/// Exploits the connector with the best observed success rate, exploring a random
+/// one with probability epsilon. Epsilon is multiplied by `decay` after every
+/// decision and never drops below `min_epsilon`.
+pub struct EpsilonGreedyRouting {
+    epsilon: f64,
+    decay: f64,
+    min_epsilon: f64,
+    arms: Arms,
+    rng: SimRng,
+}
+
+impl EpsilonGreedyRouting {
+    pub fn new(epsilon: f64, decay: f64, min_epsilon: f64, rng: SimRng) -> Self {
+        EpsilonGreedyRouting {
+            epsilon,
+            decay,
+            min_epsilon,
+            arms: Arms::default(),
+            rng,
+        }
+    }
+}
+
+impl Router for EpsilonGreedyRouting {
+    fn name(&self) -> &str {
+        "epsilon_greedy"
+    }
+
+    fn get_connector(
+        &mut self,
+        sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key> {
+        ensure!(!connectors.is_empty(), "No connectors to route to");
+        let explore = self.rng.gen_bool(self.epsilon.clamp(0.0, 1.0));
+        self.epsilon = (self.epsilon * self.decay).max(self.min_epsilon);
+        if explore {
+            return Ok(connectors[self.rng.gen_range(0..connectors.len())].clone());
+        }
+        let context = sample.payment_method_dimensions();
+        Ok(self.arms.best_by(&context, connectors, |arm| arm.mean()))
+    }
+
+    fn feedback(&mut self, feedback: &Feedback) -> Result<()> {
+        self.arms.update(feedback);
+        Ok(())
+    }
+}
+
+/// UCB1: picks the connector maximising `mean + sqrt(exploration_factor * ln(n) / n_i)`,
+/// trying every eligible connector once first.
+pub struct Ucb1Routing {
+    exploration_factor: f64,
+    arms: Arms,
+}
+
+impl Ucb1Routing {
+    pub fn new(exploration_factor: f64) -> Self {
+        Ucb1Routing {
+            exploration_factor,
+            arms: Arms::default(),
+        }
+    }
+}
+
+impl Router for Ucb1Routing {
+    fn name(&self) -> &str {
+        "ucb1"
+    }
+
+    fn get_connector(
+        &mut self,
+        sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key> {
+        ensure!(!connectors.is_empty(), "No connectors to route to");
+        let context = sample.payment_method_dimensions();
+        let total_pulls = self.arms.total_pulls(&context).max(1) as f64;
+        let exploration_factor = self.exploration_factor;
+        Ok(self.arms.best_by(&context, connectors, |arm| {
+            if arm.pulls == 0 {
+                f64::INFINITY
+            } else {
+                arm.mean() + (exploration_factor * total_pulls.ln() / arm.pulls as f64).sqrt()
+            }
+        }))
+    }
+
+    fn feedback(&mut self, feedback: &Feedback) -> Result<()> {
+        self.arms.update(feedback);
+        Ok(())
+    }
+}
+
+/// Beta-Bernoulli Thompson sampling: draws a success rate for every connector from
+/// `Beta(prior_alpha + successes, prior_beta + failures)` and picks the highest draw.
+pub struct ThompsonSamplingRouting {
+    prior_alpha: f64,
+    prior_beta: f64,
+    arms: Arms,
+    rng: SimRng,
+}
+
+impl ThompsonSamplingRouting {
+    pub fn new(prior_alpha: f64, prior_beta: f64, rng: SimRng) -> Result<Self> {
+        ensure!(
+            prior_alpha > 0.0 && prior_beta > 0.0,
+            "Thompson sampling priors must be positive"
+        );
+        Ok(ThompsonSamplingRouting {
+            prior_alpha,
+            prior_beta,
+            arms: Arms::default(),
+            rng,
+        })
+    }
+}
+
+impl Router for ThompsonSamplingRouting {
+    fn name(&self) -> &str {
+        "thompson_sampling"
+    }
+
+    fn get_connector(
+        &mut self,
+        sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key> {
+        ensure!(!connectors.is_empty(), "No connectors to route to");
+        let context = sample.payment_method_dimensions();
+        let (prior_alpha, prior_beta) = (self.prior_alpha, self.prior_beta);
+        let rng = &mut self.rng;
+        Ok(self.arms.best_by(&context, connectors, |arm| {
+            let alpha = prior_alpha + arm.successes as f64;
+            let beta = prior_beta + (arm.pulls - arm.successes) as f64;
+            // Both parameters are positive, so the distribution is always valid.
+            Beta::new(alpha, beta).map_or(0.0, |distribution| distribution.sample(rng))
+        }))
+    }
+
+    fn feedback(&mut self, feedback: &Feedback) -> Result<()> {
+        self.arms.update(feedback);
+        Ok(())
+    }
+}
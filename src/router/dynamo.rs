@@ -0,0 +1,163 @@
+use std::time::Duration;
+use anyhow::{ensure, Context, Result};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use crate::config::{Feedback, Key, Sample, Status};
+use crate::router::Router;
+
+/// Body POSTed to the decision endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DecisionRequest {
+    pub sample: Sample,
+    pub eligible_connectors: Vec<Key>,
+}
+
+/// Expected answer of the decision endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DecisionResponse {
+    pub connector: Key,
+}
+
+/// Body POSTed to the feedback endpoint once the outcome of a transaction is known.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FeedbackRequest {
+    pub connector: Key,
+    pub sample: Sample,
+    pub status: Status,
+    pub latency_ms: u64,
+}
+
+/// Delegates routing decisions to an external dynamic-routing service such as
+/// hyperswitch-dynamo. Whenever the service fails, times out or answers with a
+/// connector that isn't eligible, the `fallback` router decides instead.
+pub struct DynamoRouting {
+    client: Client,
+    decision_url: String,
+    feedback_url: Option<String>,
+    fallback: Box<dyn Router>,
+    decisions: usize,
+    fallbacks: Failures,
+    feedback_failures: Failures,
+}
+
+/// How often a request to the service failed, and the first error.
+#[derive(Default)]
+struct Failures {
+    count: usize,
+    first: Option<String>,
+}
+
+impl Failures {
+    fn add(&mut self, error: String) {
+        self.count += 1;
+        self.first.get_or_insert(error);
+    }
+}
+
+impl DynamoRouting {
+    pub fn new(
+        decision_url: String,
+        feedback_url: Option<String>,
+        timeout: Duration,
+        fallback: Box<dyn Router>,
+    ) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to build HTTP client for dynamo routing")?;
+        Ok(DynamoRouting {
+            client,
+            decision_url,
+            feedback_url,
+            fallback,
+            decisions: 0,
+            fallbacks: Failures::default(),
+            feedback_failures: Failures::default(),
+        })
+    }
+
+    fn decide(&self, sample: &Sample, connectors: &[Key]) -> Result<Key> {
+        let request = DecisionRequest {
+            sample: sample.clone(),
+            eligible_connectors: connectors.to_vec(),
+        };
+        let response: DecisionResponse = self
+            .client
+            .post(&self.decision_url)
+            .json(&request)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Decision request to {} failed", self.decision_url))?
+            .json()
+            .context("Invalid decision response")?;
+        ensure!(
+            connectors.contains(&response.connector),
+            "Decision service chose ineligible connector {:?}",
+            response.connector.0
+        );
+        Ok(response.connector)
+    }
+}
+
+impl Router for DynamoRouting {
+    fn name(&self) -> &str {
+        "dynamo"
+    }
+
+    fn get_connector(
+        &mut self,
+        sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key> {
+        self.decisions += 1;
+        match self.decide(sample, connectors) {
+            Ok(connector) => Ok(connector),
+            Err(error) => {
+                self.fallbacks.add(format!("{:#}", error));
+                self.fallback.get_connector(sample, connectors)
+            }
+        }
+    }
+
+    fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(error) = &self.fallbacks.first {
+            lines.push(format!(
+                "Dynamo routing: fell back to {} routing for {} of {} decisions (first error: {})",
+                self.fallback.name(),
+                self.fallbacks.count,
+                self.decisions,
+                error
+            ));
+        }
+        if let Some(error) = &self.feedback_failures.first {
+            lines.push(format!(
+                "Dynamo routing: {} feedback requests failed (first error: {})",
+                self.feedback_failures.count, error
+            ));
+        }
+        lines.extend(self.fallback.summary());
+        lines
+    }
+
+    fn feedback(&mut self, feedback: &Feedback) -> Result<()> {
+        if let Some(feedback_url) = &self.feedback_url {
+            let request = FeedbackRequest {
+                connector: feedback.connector.clone(),
+                sample: feedback.sample.clone(),
+                status: feedback.status.clone(),
+                latency_ms: feedback.latency.as_millis() as u64,
+            };
+            let response = self
+                .client
+                .post(feedback_url)
+                .json(&request)
+                .send()
+                .and_then(|response| response.error_for_status());
+            if let Err(error) = response {
+                self.feedback_failures.add(format!("request to {} failed: {}", feedback_url, error));
+            }
+        }
+        self.fallback.feedback(feedback)
+    }
+}
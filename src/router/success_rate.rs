@@ -0,0 +1,155 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use anyhow::{ensure, Result};
+use rand::Rng;
+use crate::config::{Feedback, Key, Sample, SlidingWindow, Status};
+use crate::router::Router;
+use crate::rng::SimRng;
+
+/// (connector, payment method, payment method type)
+type WindowKey = (Key, String, String);
+
+/// Routes to the eligible connector with the best recent success rate for the
+/// sample's payment method, exploring a random connector `exploration_percentage`%
+/// of the time.
+pub struct SuccessRateRouting {
+    window: SlidingWindow,
+    exploration_percentage: u8,
+    outcomes: HashMap<WindowKey, VecDeque<(Duration, bool)>>,
+    /// Current simulated time: the latest arrival routed or response fed back.
+    now: Duration,
+    rng: SimRng,
+}
+
+impl SuccessRateRouting {
+    pub fn new(window: SlidingWindow, exploration_percentage: u8, rng: SimRng) -> Self {
+        SuccessRateRouting {
+            window,
+            exploration_percentage,
+            outcomes: HashMap::new(),
+            now: Duration::ZERO,
+            rng,
+        }
+    }
+
+    /// Laplace-smoothed success rate, so connectors without history score 0.5.
+    fn score(&self, key: &WindowKey) -> f64 {
+        let (successes, total) = self
+            .outcomes
+            .get(key)
+            .map(|window| {
+                let in_window = window.iter().filter(|(at, _)| self.in_window(*at));
+                in_window.fold((0, 0), |(successes, total), (_, success)| {
+                    (successes + usize::from(*success), total + 1)
+                })
+            })
+            .unwrap_or((0, 0));
+        (successes as f64 + 1.0) / (total as f64 + 2.0)
+    }
+
+    fn in_window(&self, at: Duration) -> bool {
+        match self.window {
+            SlidingWindow::LastTransactions(_) => true,
+            SlidingWindow::LastSeconds(seconds) => self.now.saturating_sub(at) <= Duration::from_secs(seconds),
+        }
+    }
+
+    /// Drop the outcomes of `key` that have left the window as of `now`.
+    fn prune(&mut self, key: &WindowKey) {
+        let Some(window) = self.outcomes.get_mut(key) else {
+            return;
+        };
+        match self.window {
+            SlidingWindow::LastTransactions(size) => {
+                while window.len() > size {
+                    window.pop_front();
+                }
+            }
+            SlidingWindow::LastSeconds(seconds) => {
+                let horizon = self.now.saturating_sub(Duration::from_secs(seconds));
+                while window.front().is_some_and(|(at, _)| *at < horizon) {
+                    window.pop_front();
+                }
+            }
+        }
+    }
+}
+
+impl Router for SuccessRateRouting {
+    fn name(&self) -> &str {
+        "success_rate"
+    }
+
+    fn get_connector(
+        &mut self,
+        sample: &Sample,
+        connectors: &[Key],
+    ) -> Result<Key> {
+        ensure!(!connectors.is_empty(), "No connectors to route to");
+        if self.rng.gen_range(0..100) < self.exploration_percentage {
+            return Ok(connectors[self.rng.gen_range(0..connectors.len())].clone());
+        }
+
+        self.now = self.now.max(Duration::from_millis(sample.timestamp_ms));
+        let (payment_method, payment_method_type) = sample.payment_method_dimensions();
+        let mut best = &connectors[0];
+        let mut best_score = f64::MIN;
+        for connector in connectors {
+            let key = (connector.clone(), payment_method.clone(), payment_method_type.clone());
+            self.prune(&key);
+            let score = self.score(&key);
+            if score > best_score {
+                best = connector;
+                best_score = score;
+            }
+        }
+        Ok(best.clone())
+    }
+
+    fn feedback(&mut self, feedback: &Feedback) -> Result<()> {
+        let (payment_method, payment_method_type) = feedback.sample.payment_method_dimensions();
+        let key = (feedback.connector.clone(), payment_method, payment_method_type);
+        self.now = self.now.max(feedback.timestamp);
+
+        let window = self.outcomes.entry(key.clone()).or_default();
+        window.push_back((feedback.timestamp, feedback.status == Status::Success));
+        self.prune(&key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::{stream, Stream};
+
+    fn card(timestamp_ms: u64) -> Sample {
+        Sample {
+            amount: 1000,
+            currency: "USD".to_string(),
+            payment_method: Key("card".to_string()),
+            payment_method_type: None,
+            attributes: Default::default(),
+            timestamp_ms,
+        }
+    }
+
+    #[test]
+    fn last_seconds_window_follows_simulated_time_without_new_feedback() {
+        let mut router = SuccessRateRouting::new(SlidingWindow::LastSeconds(10), 0, stream(0, Stream::Router));
+        let connectors = [Key("adyen".to_string()), Key("stripe".to_string())];
+        router
+            .feedback(&Feedback {
+                connector: connectors[0].clone(),
+                sample: card(500),
+                status: Status::Failure,
+                latency: Duration::from_millis(500),
+                timestamp: Duration::from_secs(1),
+            })
+            .unwrap();
+        assert_eq!(router.get_connector(&card(2_000), &connectors).unwrap(), connectors[1]);
+        // The failure is more than 10s old by now, so both connectors score alike and
+        // the first one wins.
+        assert_eq!(router.get_connector(&card(20_000), &connectors).unwrap(), connectors[0]);
+    }
+}
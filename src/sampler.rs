@@ -1,49 +1,47 @@
 use std::collections::HashMap;
-use crate::config::{AmountRange, Key, PaymentMethods, SimulationConfig};
+use crate::config::{sorted, AmountRange, Key, PaymentMethodDetails, PaymentMethods, Sample, SimulationConfig};
 use anyhow::{anyhow, Result};
 use rand::Rng;
+use serde_json::Value;
         
 pub trait Sampler {
-    fn generate_sample(&self) -> Result<HashMap<Key, Key>>;
+    fn generate_sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Sample>;
 
-    fn list_payment_methods(config: &SimulationConfig) -> Result<HashMap<&Key, &Key>> {
-        config
-            .iter()
+    /// Pick a value for every dimension of the config, e.g. `payment_methods` and,
+    /// for composite choices, their nested dimensions and `extra_fields`.
+    fn list_payment_methods<R: Rng + ?Sized>(config: &SimulationConfig, rng: &mut R) -> Result<HashMap<Key, Value>> {
+        // Walk the config in key order so that a seeded rng always yields the same sample.
+        sorted(config)
+            .into_iter()
             .try_fold(HashMap::new(), |mut acc, (key, payment_method)| {
-                let (value, next) = Self::choose_payment_method(payment_method)?;
-                acc.insert(key, value);
-                if let Some(next) = next {
-                    let next = Self::list_payment_methods(next)?;
+                let (value, details) = Self::choose_payment_method(payment_method, rng)?;
+                acc.insert(key.clone(), Value::String(value.0.clone()));
+                if let PaymentMethodDetails::Composite { next, extra_fields, .. } = details {
+                    if let Some(extra_fields) = extra_fields {
+                        acc.extend(extra_fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    }
+                    let next = Self::list_payment_methods(next, rng)?;
                     acc.extend(next);
                 }
                 Ok(acc)
         })
     }
 
-    fn choose_payment_method(payment_method: &PaymentMethods) -> Result<(&Key, Option<&SimulationConfig>)> {
-        let mut rng = rand::thread_rng();
+    fn choose_payment_method<'a, R: Rng + ?Sized>(payment_method: &'a PaymentMethods, rng: &mut R) -> Result<(&'a Key, &'a PaymentMethodDetails)> {
         let mut number = rng.gen_range(0..100);
-        let variants = payment_method.iter().fold(None, |acc, (key, info)| {
+        let variants = sorted(payment_method).into_iter().fold(None, |acc, (key, info)| {
             if acc.is_some() {
                 acc
             } else {
-                match info {
-                    crate::config::PaymentMethodDetails::Percentage(val) => {
-                        if number < *val {
-                            Some((key, None))
-                        } else {
-                            number -= val;
-                            None
-                        }
-                    }
-                    crate::config::PaymentMethodDetails::Composite { percentage, next, extra_fields: _ } => {
-                        if number < *percentage {
-                            Some((key, Some(next)))
-                        } else {
-                            number -= percentage;
-                            None
-                        }
-                    }
+                let percentage = match info {
+                    PaymentMethodDetails::Percentage(val) => *val,
+                    PaymentMethodDetails::Composite { percentage, .. } => *percentage,
+                };
+                if number < percentage {
+                    Some((key, info))
+                } else {
+                    number -= percentage;
+                    None
                 }
             }
         });
@@ -51,9 +49,7 @@ pub trait Sampler {
         Ok(output)
     }
 
-    fn generate_random_amount(amount_range: &Option<AmountRange>) -> u32 {
-        let range = amount_range.as_ref().unwrap_or(&AmountRange { min: 0, max: 2000 });
-        let mut rng = rand::thread_rng();
-        rng.gen_range(range.min..=range.max)
+    fn generate_random_amount<R: Rng + ?Sized>(range: &AmountRange, rng: &mut R) -> u64 {
+        rng.gen_range(range.min as u64..=range.max as u64)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,393 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use crate::arrival::Arrivals;
+use crate::config::{
+    connector_eligibility, unroutable_reason, Eligibility, Config, Feedback, Key, Metrics, Outcome, PaymentRecorderData, RoutingConfig, Sample, SimTime, Status,
+};
+use crate::evaluator::Evaluator;
+use crate::event::EventQueue;
+use crate::recorder::Recorder;
+use crate::rng::{stream, SimRng, Stream};
+use crate::router::{FeedbackQueue, GroundTruth, Router};
+use crate::sampler::Sampler;
+
+/// Runs transactions against a config that is parsed once: samples a payment,
+/// routes it, evaluates it against the PSP config, feeds the outcome back to the
+/// router and hands it to the recorders.
+///
+/// Payments arrive, get their PSP responses and have their feedback delivered as
+/// events ordered by simulated time, so payments overlap whenever one arrives before
+/// the responses of earlier ones: the router then decides without their outcomes.
+pub struct Simulation {
+    config: Config,
+    router: Box<dyn Router>,
+    feedback_queue: FeedbackQueue,
+    recorders: Vec<Box<dyn Recorder>>,
+    metrics: Metrics,
+    seed: u64,
+    sampler_rng: SimRng,
+    evaluator_rng: SimRng,
+    arrivals: Arrivals,
+    events: EventQueue<Event>,
+    /// Number of payments that have arrived.
+    transaction: u64,
+    /// Number of payments that will have arrived once the current run is over.
+    end: u64,
+    in_flight: usize,
+    peak_in_flight: usize,
+    /// Time of the event being handled, or of the last one once a run is over.
+    clock: Duration,
+    /// Time the last payment was completed or found unroutable.
+    completed_at: Duration,
+    /// Point of the run the decision being made is evaluated at, shared with the oracle.
+    at: Rc<Cell<SimTime>>,
+    /// Arrival time and sample of every payment so far, when kept for `with_replay`.
+    arrival_log: Option<Vec<(Duration, Sample)>>,
+    /// Arrivals still to replay, used instead of the sampler and the arrival process.
+    replay: Option<VecDeque<(Duration, Sample)>>,
+    classification: bool,
+    verbose: bool,
+}
+
+impl Simulation {
+    /// Simulation of `config` routed by `routing`, with the router, sampler, evaluator
+    /// and arrivals drawing from streams of `seed`.
+    pub fn new(mut config: Config, routing: &RoutingConfig, seed: u64) -> Result<Self> {
+        Rc::get_mut(&mut config.psp).context("PSP config is already shared with a router")?.seed = seed;
+        let at = Rc::new(Cell::new(SimTime::default()));
+        let truth = GroundTruth { psp: Rc::clone(&config.psp), merchant: Rc::clone(&config.merchant), at: Rc::clone(&at) };
+        let router = routing.build(stream(seed, Stream::Router), &truth)?;
+        Ok(Simulation {
+            arrivals: Arrivals::new(config.arrivals, stream(seed, Stream::Arrivals))?,
+            feedback_queue: FeedbackQueue::new(config.feedback.delay),
+            classification: config.classification,
+            config,
+            router,
+            recorders: Vec::new(),
+            metrics: Metrics::new(),
+            seed,
+            sampler_rng: stream(seed, Stream::Sampler),
+            evaluator_rng: stream(seed, Stream::Evaluator),
+            events: EventQueue::new(),
+            transaction: 0,
+            end: 0,
+            in_flight: 0,
+            peak_in_flight: 0,
+            clock: Duration::ZERO,
+            completed_at: Duration::ZERO,
+            at,
+            arrival_log: None,
+            replay: None,
+            verbose: false,
+        })
+    }
+
+    /// Also hand every transaction to `recorder`, in addition to the built-in `Metrics`.
+    pub fn with_recorder(mut self, recorder: Box<dyn Recorder>) -> Self {
+        self.recorders.push(recorder);
+        self
+    }
+
+    /// Label and classify every transaction, even if the config doesn't ask for it.
+    pub fn with_classification(mut self, classification: bool) -> Self {
+        self.classification |= classification;
+        self
+    }
+
+    /// Print the details of every transaction.
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Keep the arrival time and sample of every payment, see `arrival_log`.
+    pub fn with_arrival_log(mut self) -> Self {
+        self.arrival_log = Some(Vec::new());
+        self
+    }
+
+    /// Payments arrive at the times and with the samples of `arrivals`, e.g. the
+    /// `arrival_log` of another simulation, instead of being drawn.
+    pub fn with_replay(mut self, arrivals: &[(Duration, Sample)]) -> Self {
+        self.replay = Some(arrivals.iter().cloned().collect());
+        self
+    }
+
+    /// Arrival time and sample of every payment so far, if `with_arrival_log` was set.
+    pub fn arrival_log(&self) -> Option<&[(Duration, Sample)]> {
+        self.arrival_log.as_deref()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Simulated time from the start of the run to the completion of its last payment;
+    /// feedback delivered later doesn't count.
+    pub fn elapsed(&self) -> Duration {
+        self.completed_at
+    }
+
+    pub fn router(&self) -> &dyn Router {
+        self.router.as_ref()
+    }
+
+    /// Largest number of payments waiting for a PSP response at the same time.
+    pub fn peak_in_flight(&self) -> usize {
+        self.peak_in_flight
+    }
+
+    /// Also count the transactions of every `bucket` of simulated time in the metrics.
+    pub fn with_time_buckets(mut self, bucket: Option<Duration>) -> Self {
+        self.metrics.bucket = bucket;
+        self
+    }
+
+    /// Run `iterations` transactions, then deliver any pending feedback and flush the recorders.
+    pub fn run(&mut self, iterations: u64) -> Result<()> {
+        self.end = self.transaction + iterations;
+        if iterations > 0 {
+            self.schedule_next_arrival();
+        }
+        while let Some((time, event)) = self.events.pop() {
+            self.clock = time;
+            match event {
+                Event::Arrival => self.arrive()?,
+                Event::Response(payment) => self.respond(*payment)?,
+                Event::FeedbackDue => {
+                    self.feedback_queue.deliver(self.router.as_mut(), self.transaction, self.clock)?
+                }
+            }
+        }
+        self.finish()
+    }
+
+    /// Deliver pending feedback and flush the recorders.
+    pub fn finish(&mut self) -> Result<()> {
+        self.feedback_queue.flush(self.router.as_mut())?;
+        for recorder in self.recorders.iter_mut() {
+            recorder.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sample the payment arriving now and send its first attempt.
+    fn arrive(&mut self) -> Result<()> {
+        let transaction = self.transaction;
+        self.transaction += 1;
+        let mut sample = match self.replay.as_mut().and_then(VecDeque::pop_front) {
+            Some((_, sample)) => sample,
+            None => self.config.user.generate_sample(&mut self.sampler_rng)?,
+        };
+        if !self.is_sequential() {
+            self.schedule_next_arrival();
+        }
+        sample.timestamp_ms = self.clock.as_millis() as u64;
+        if let Some(log) = self.arrival_log.as_mut() {
+            log.push((self.clock, sample.clone()));
+        }
+        let eligibility = connector_eligibility(&sample, &self.config.merchant);
+        if self.verbose {
+            println!("Payment {} arrived at {}ms: {}", transaction, sample.timestamp_ms, serde_json::to_string_pretty(&sample)?);
+            println!("Connector eligibility:");
+            for (connector, eligibility) in &eligibility {
+                println!("{}: {}", connector.0, eligibility);
+            }
+        }
+        let connectors: Vec<Key> = eligibility
+            .iter()
+            .filter(|(_, eligibility)| *eligibility == Eligibility::Eligible)
+            .map(|(connector, _)| connector.clone())
+            .collect();
+        if connectors.is_empty() {
+            let reason = unroutable_reason(&eligibility);
+            if self.verbose {
+                println!("No connectors available for this user in merchant config ({}).", reason);
+            }
+            self.completed_at = self.clock;
+            self.metrics.record_unroutable(&sample, reason)?;
+            for recorder in self.recorders.iter_mut() {
+                recorder.record_unroutable(&sample, reason)?;
+            }
+            if self.is_sequential() {
+                self.schedule_next_arrival();
+            }
+            return Ok(());
+        }
+
+        self.in_flight += 1;
+        self.peak_in_flight = self.peak_in_flight.max(self.in_flight);
+        let payment = Payment {
+            transaction,
+            sample,
+            candidates: connectors,
+            attempts: Vec::new(),
+        };
+        self.send(payment)
+    }
+
+    /// Route the next attempt of `payment` and schedule the PSP response to it.
+    fn send(&mut self, mut payment: Payment) -> Result<()> {
+        let attempt = payment.attempts.len() as u32 + 1;
+        let router = self.router.as_mut();
+        // A retry of an older payment is routed after later arrivals, so the latest
+        // arrival decides which feedback is due, not `payment.transaction`.
+        self.feedback_queue.deliver(router, self.transaction - 1, self.clock)?;
+        let at = SimTime { elapsed: self.clock, transaction: payment.transaction };
+        let expected: Vec<(Key, f64)> = payment
+            .candidates
+            .iter()
+            .map(|connector| {
+                let timeout = self.config.merchant.timeout(connector);
+                (connector.clone(), self.config.psp.expected_success(connector, &payment.sample, at, timeout))
+            })
+            .collect();
+        self.at.set(at);
+        let connector = router.get_connector(&payment.sample, &payment.candidates)?;
+        let best_expected = expected.iter().map(|(_, expected)| *expected).fold(0.0, f64::max);
+        let chosen_expected = expected
+            .iter()
+            .find(|(candidate, _)| *candidate == connector)
+            .map_or(0.0, |(_, expected)| *expected);
+        let label = if self.classification {
+            self.config.psp.best_connector(&payment.candidates, &payment.sample, at, &self.config.merchant)
+        } else {
+            None
+        };
+        if self.verbose {
+            println!(
+                "Payment {}: using connector {:?} (routing: {}, attempt {})",
+                payment.transaction, connector.0, router.name(), attempt
+            );
+        }
+
+        // The outcome is drawn now but only takes effect when the response event is handled.
+        let psp = &self.config.psp;
+        let Outcome { status, latency, failure_reason } = psp
+            .call_evaluator(&connector, &payment.sample, at, &mut self.evaluator_rng)?
+            .with_timeout(self.config.merchant.timeout(&connector));
+        if !self.config.retry.allow_same_connector {
+            payment.candidates.retain(|candidate| candidate != &connector);
+        }
+        payment.attempts.push(
+            PaymentRecorderData::set_values(connector, status, payment.sample.clone())
+                .with_label(label)
+                .with_latency(latency)
+                .with_failure_reason(failure_reason)
+                .with_attempt(attempt)
+                .with_timestamp(self.clock)
+                .with_regret(best_expected - chosen_expected),
+        );
+        self.events.schedule(self.clock + latency, Event::Response(Box::new(payment)));
+        Ok(())
+    }
+
+    /// Handle the PSP response to the last attempt of `payment`: queue its feedback,
+    /// then retry or complete the payment.
+    fn respond(&mut self, payment: Payment) -> Result<()> {
+        let Some(data) = payment.attempts.last() else {
+            return Ok(());
+        };
+        if self.verbose {
+            let latency = data.latency.as_millis();
+            match (&data.verdict, &data.failure_reason) {
+                (Status::Success, _) => println!("Payment {}: succeeded in {}ms.", payment.transaction, latency),
+                (Status::Failure, Some(reason)) => {
+                    println!("Payment {}: failed ({}) in {}ms.", payment.transaction, reason.0, latency)
+                }
+                (Status::Failure, None) => println!("Payment {}: failed in {}ms.", payment.transaction, latency),
+                (Status::Timeout, _) => println!("Payment {}: timed out after {}ms.", payment.transaction, latency),
+            }
+        }
+
+        let feedback = Feedback {
+            connector: data.connector.clone(),
+            sample: payment.sample.clone(),
+            status: data.verdict.clone(),
+            latency: data.latency,
+            timestamp: self.clock,
+        };
+        let due = self.feedback_queue.push(feedback, payment.transaction, self.clock);
+        if due > self.clock {
+            self.events.schedule(due, Event::FeedbackDue);
+        } else {
+            self.feedback_queue.deliver(self.router.as_mut(), self.transaction - 1, self.clock)?;
+        }
+
+        let retry = &self.config.retry;
+        if retry.is_retryable(&data.verdict, data.failure_reason.as_ref())
+            && (payment.attempts.len() as u32) < retry.max_attempts
+            && !payment.candidates.is_empty()
+        {
+            return self.send(payment);
+        }
+        self.complete(payment)
+    }
+
+    /// Record every attempt of a payment with its final status.
+    fn complete(&mut self, payment: Payment) -> Result<()> {
+        self.in_flight -= 1;
+        self.completed_at = self.clock;
+        let final_verdict = payment.attempts.last().map(|data| data.verdict.clone()).unwrap_or(Status::Failure);
+        for record_data in payment.attempts {
+            let record_data = record_data.with_final_verdict(final_verdict.clone());
+            self.metrics.record_transaction(&record_data)?;
+            for recorder in self.recorders.iter_mut() {
+                recorder.record_transaction(&record_data)?;
+            }
+        }
+        if self.is_sequential() {
+            self.schedule_next_arrival();
+        }
+        Ok(())
+    }
+
+    /// Whether the next payment arrives only once the previous one has completed;
+    /// replayed payments arrive at their logged times instead.
+    fn is_sequential(&self) -> bool {
+        self.replay.is_none() && self.arrivals.is_sequential()
+    }
+
+    fn schedule_next_arrival(&mut self) {
+        if self.transaction >= self.end {
+            return;
+        }
+        let arrival = match &self.replay {
+            Some(replay) => match replay.front() {
+                Some((arrival, _)) => *arrival,
+                None => return,
+            },
+            None => self.arrivals.next_arrival(self.clock),
+        };
+        self.events.schedule(arrival, Event::Arrival);
+    }
+}
+
+/// A payment between its arrival and the response to its last attempt.
+struct Payment {
+    transaction: u64,
+    sample: Sample,
+    /// Connectors the next attempt may be routed to.
+    candidates: Vec<Key>,
+    attempts: Vec<PaymentRecorderData>,
+}
+
+enum Event {
+    /// The next payment arrives.
+    Arrival,
+    /// The PSP answers the last attempt of a payment.
+    Response(Box<Payment>),
+    /// Feedback held back by the configured delay becomes due.
+    FeedbackDue,
+}
@@ -0,0 +1,114 @@
+/// Two-sided 95% quantile of the standard normal distribution.
+pub const Z_95: f64 = 1.959964;
+
+/// Transactions below which a success rate is flagged as too uncertain to act on.
+pub const DEFAULT_MIN_TRANSACTIONS: usize = 30;
+
+/// Wilson score 95% confidence interval of a success rate; unlike the normal
+/// approximation it stays within [0, 1] and works for small samples and 0% / 100%.
+pub fn wilson_interval(successes: usize, trials: usize) -> Option<(f64, f64)> {
+    if trials == 0 {
+        return None;
+    }
+    let n = trials as f64;
+    let rate = successes as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let center = rate + z2 / (2.0 * n);
+    let margin = Z_95 * (rate * (1.0 - rate) / n + z2 / (4.0 * n * n)).sqrt();
+    let denominator = 1.0 + z2 / n;
+    Some((((center - margin) / denominator).max(0.0), ((center + margin) / denominator).min(1.0)))
+}
+
+/// Two-sided p-value of a pooled two-proportion z-test of whether two samples of
+/// `(successes, trials)` have the same success rate.
+pub fn two_proportion_p_value(a: (usize, usize), b: (usize, usize)) -> Option<f64> {
+    let (a_successes, a_trials) = a;
+    let (b_successes, b_trials) = b;
+    if a_trials == 0 || b_trials == 0 {
+        return None;
+    }
+    let pooled = (a_successes + b_successes) as f64 / (a_trials + b_trials) as f64;
+    let standard_error = (pooled * (1.0 - pooled) * (1.0 / a_trials as f64 + 1.0 / b_trials as f64)).sqrt();
+    let difference = b_successes as f64 / b_trials as f64 - a_successes as f64 / a_trials as f64;
+    if standard_error == 0.0 {
+        // Both samples all successes or all failures.
+        return Some(if difference == 0.0 { 1.0 } else { 0.0 });
+    }
+    let z = (difference / standard_error).abs();
+    Some((2.0 * (1.0 - normal_cdf(z))).clamp(0.0, 1.0))
+}
+
+/// Cumulative distribution function of the standard normal distribution.
+pub(crate) fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26, accurate to 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - polynomial * (-x * x).exp();
+    if x < 0.0 { -y } else { y }
+}
+
+/// Difference `b - a` between the success rates of two independent samples of
+/// `(successes, trials)`, with the half-width of its 95% normal approximation
+/// confidence interval.
+pub fn rate_difference(a: (usize, usize), b: (usize, usize)) -> Option<(f64, f64)> {
+    let (a_successes, a_trials) = a;
+    let (b_successes, b_trials) = b;
+    if a_trials == 0 || b_trials == 0 {
+        return None;
+    }
+    let a_rate = a_successes as f64 / a_trials as f64;
+    let b_rate = b_successes as f64 / b_trials as f64;
+    let variance = a_rate * (1.0 - a_rate) / a_trials as f64 + b_rate * (1.0 - b_rate) / b_trials as f64;
+    Some((b_rate - a_rate, Z_95 * variance.sqrt()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-4, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn wilson_interval_matches_reference_values() {
+        // Newcombe (1998), example 81/263: 0.2553-0.3662.
+        let (low, high) = wilson_interval(81, 263).unwrap();
+        assert_close(low, 0.2553);
+        assert_close(high, 0.3662);
+        let (low, high) = wilson_interval(8, 10).unwrap();
+        assert_close(low, 0.4902);
+        assert_close(high, 0.9433);
+        let (low, high) = wilson_interval(0, 10).unwrap();
+        assert_close(low, 0.0);
+        assert_close(high, 0.2775);
+        assert_eq!(wilson_interval(0, 0), None);
+    }
+
+    #[test]
+    fn two_proportion_p_value_matches_reference_values() {
+        // 45/100 against 60/100: z = 2.1240, two-sided p = 0.0337.
+        assert_close(two_proportion_p_value((45, 100), (60, 100)).unwrap(), 0.0337);
+        assert_close(two_proportion_p_value((60, 100), (45, 100)).unwrap(), 0.0337);
+        assert_close(two_proportion_p_value((50, 100), (50, 100)).unwrap(), 1.0);
+        assert_eq!(two_proportion_p_value((10, 10), (10, 10)), Some(1.0));
+        assert!(two_proportion_p_value((0, 10), (10, 10)).unwrap() < 1e-4);
+        assert_eq!(two_proportion_p_value((0, 0), (10, 10)), None);
+    }
+
+    #[test]
+    fn rate_difference_matches_reference_values() {
+        // 60/100 - 45/100 = 0.15, with a 95% Wald half-width of 0.1368.
+        let (difference, half_width) = rate_difference((45, 100), (60, 100)).unwrap();
+        assert_close(difference, 0.15);
+        assert_close(half_width, 0.1368);
+        let (difference, half_width) = rate_difference((60, 100), (45, 100)).unwrap();
+        assert_close(difference, -0.15);
+        assert_close(half_width, 0.1368);
+        assert_eq!(rate_difference((5, 10), (0, 0)), None);
+    }
+}
@@ -0,0 +1,456 @@
+use std::fmt;
+use crate::behaviour::{Behaviours, Predicate};
+use crate::config::{
+    sorted, ArrivalProcess, Config, Key, PaymentMethodDetails, PaymentMethodTypes, PaymentMethods, RoutingConfig,
+    SimulationConfig, SlidingWindow, SrChangeKind,
+};
+
+/// A problem found by `Config::validate`, at the JSON path of the offending value,
+/// e.g. `psp.psp_variants.adyen.payment_methods.card[0].sr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Collects every problem instead of stopping at the first one.
+#[derive(Default)]
+struct Problems(Vec<ConfigError>);
+
+impl Problems {
+    fn check(&mut self, ok: bool, path: impl Into<String>, message: impl Into<String>) {
+        if !ok {
+            self.0.push(ConfigError { path: path.into(), message: message.into() });
+        }
+    }
+}
+
+impl Config {
+    /// Check the user, psp and merchant blocks against each other, reporting every
+    /// problem at once; an empty list means the config is valid.
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut problems = Problems::default();
+        self.validate_user(&mut problems);
+        self.validate_psp(&mut problems);
+        self.validate_merchant(&mut problems);
+        self.validate_arrivals(&mut problems);
+        self.routing.validate("routing", &mut problems);
+        problems.check(self.retry.max_attempts >= 1, "retry.max_attempts", "must be at least 1");
+        problems.0
+    }
+
+    fn validate_arrivals(&self, problems: &mut Problems) {
+        let check_tps = |problems: &mut Problems, field: &str, tps: f64| {
+            problems.check(tps > 0.0 && tps.is_finite(), format!("arrivals.{}", field), "must be positive");
+        };
+        match self.arrivals {
+            ArrivalProcess::Sequential => {}
+            ArrivalProcess::Constant { tps } | ArrivalProcess::Poisson { tps } => check_tps(problems, "tps", tps),
+            ArrivalProcess::Diurnal { tps, amplitude, peak_hour } => {
+                check_tps(problems, "tps", tps);
+                problems.check((0.0..=1.0).contains(&amplitude), "arrivals.amplitude", "must be between 0 and 1");
+                problems.check((0.0..24.0).contains(&peak_hour), "arrivals.peak_hour", "must be between 0 and 24");
+            }
+            ArrivalProcess::Bursts { tps, burst_tps, every_ms, duration_ms } => {
+                check_tps(problems, "tps", tps);
+                check_tps(problems, "burst_tps", burst_tps);
+                problems.check(every_ms > 0, "arrivals.every_ms", "must be positive");
+                problems.check(duration_ms <= every_ms, "arrivals.duration_ms", "must be at most every_ms");
+            }
+        }
+    }
+
+    fn validate_user(&self, problems: &mut Problems) {
+        if let Some(amount) = &self.user.amount {
+            problems.check(
+                amount.min <= amount.max,
+                "user.amount",
+                format!("min ({}) is greater than max ({})", amount.min, amount.max),
+            );
+        }
+        self.user.payment_methods.validate("user", problems);
+        self.validate_currencies(problems);
+
+        // Every payment method (and type) the user can sample needs a connector.
+        let Some(payment_methods) = self.user.payment_methods.get(&Key("payment_methods".to_string())) else {
+            return;
+        };
+        let connectors = &self.merchant.connectors_list;
+        for (payment_method, details) in sorted(payment_methods) {
+            if percentage(details) == 0 {
+                continue;
+            }
+            let path = format!("user.payment_methods.{}", payment_method.0);
+            let supporting: Vec<_> = sorted(connectors)
+                .into_iter()
+                .filter_map(|(_, connector)| connector.supported_payment_methods.get(payment_method))
+                .collect();
+            problems.check(
+                !supporting.is_empty(),
+                &path,
+                "not supported by any connector in merchant.connectors_list",
+            );
+            let PaymentMethodDetails::Composite { next, .. } = details else {
+                continue;
+            };
+            let Some(types) = next.get(&Key("payment_method_type".to_string())) else {
+                continue;
+            };
+            if supporting.is_empty() {
+                continue;
+            }
+            for (payment_method_type, details) in sorted(types) {
+                if percentage(details) == 0 {
+                    continue;
+                }
+                let supported = supporting.iter().any(|config| {
+                    config
+                        .payment_method_types
+                        .as_ref()
+                        .is_none_or(|types| types.contains(&payment_method_type.0))
+                });
+                problems.check(
+                    supported,
+                    format!("{}.next.payment_method_type.{}", path, payment_method_type.0),
+                    "not supported by any connector in merchant.connectors_list",
+                );
+            }
+        }
+    }
+
+    fn validate_currencies(&self, problems: &mut Problems) {
+        if let Some(currencies) = &self.user.currencies {
+            problems.check(self.user.currency.is_none(), "user.currency", "can't be combined with user.currencies");
+            let total: u32 = currencies.values().map(|details| details.percentage as u32).sum();
+            problems.check(total == 100, "user.currencies", format!("percentages add up to {}, not 100", total));
+            for (currency, details) in sorted(currencies) {
+                let path = format!("user.currencies.{}", currency.0);
+                if let Some(amount) = &details.amount {
+                    problems.check(
+                        amount.min <= amount.max,
+                        format!("{}.amount", path),
+                        format!("min ({}) is greater than max ({})", amount.min, amount.max),
+                    );
+                }
+                problems.check(details.minor_units <= 4, format!("{}.minor_units", path), "must be at most 4");
+            }
+        }
+
+        // Every currency the user can pay in needs a connector.
+        let connectors = &self.merchant.connectors_list;
+        for currency in self.user.currency_mix() {
+            if currency.share == 0.0 {
+                continue;
+            }
+            let path = match self.user.currencies {
+                Some(_) => format!("user.currencies.{}", currency.currency),
+                None => "user.currency".to_string(),
+            };
+            let supported = connectors.values().any(|connector| {
+                connector
+                    .supported_currencies
+                    .as_ref()
+                    .is_none_or(|currencies| currencies.contains(&currency.currency))
+            });
+            problems.check(supported, path, "not supported by any connector in merchant.connectors_list");
+        }
+    }
+
+    fn validate_psp(&self, problems: &mut Problems) {
+        if let Some(otherwise) = &self.psp.otherwise {
+            problems.check(
+                otherwise == "success" || otherwise == "failure",
+                "psp.otherwise",
+                format!("must be \"success\" or \"failure\", not {:?}", otherwise),
+            );
+        }
+        for (connector, details) in sorted(&self.psp.psp_variants) {
+            let path = format!("psp.psp_variants.{}", connector.0);
+            for (payment_method, types) in sorted(&details.payment_methods) {
+                let path = format!("{}.payment_methods.{}", path, payment_method.0);
+                check_payment_method_srs(problems, &path, types);
+            }
+            for (currency, payment_methods) in sorted(&details.currencies) {
+                for (payment_method, types) in sorted(payment_methods) {
+                    let path = format!("{}.currencies.{}.{}", path, currency.0, payment_method.0);
+                    check_payment_method_srs(problems, &path, types);
+                }
+            }
+            if let Some(tail) = details.psp_time_config.as_ref().and_then(|time| time.tail) {
+                let path = format!("{}.psp_time_config.tail", path);
+                check_sr(problems, format!("{}.percentage", path), tail.percentage);
+                problems.check(tail.multiplier >= 1.0, format!("{}.multiplier", path), "must be at least 1");
+            }
+            for (payment_method, reasons) in sorted(&details.failure_reasons) {
+                problems.check(
+                    reasons.values().any(|weight| *weight > 0),
+                    format!("{}.failure_reasons.{}", path, payment_method.0),
+                    "needs at least one reason with a positive weight",
+                );
+            }
+            for (index, change) in details.sr_schedule.iter().enumerate() {
+                let path = format!("{}.sr_schedule[{}]", path, index);
+                match change.change {
+                    SrChangeKind::Step { sr, .. } | SrChangeKind::Incidents { sr, .. } => {
+                        check_sr(problems, format!("{}.sr", path), sr)
+                    }
+                    SrChangeKind::Drift { start, end, to } => {
+                        check_sr(problems, format!("{}.to", path), to);
+                        problems.check(start < end, format!("{}.end", path), "must be after start");
+                    }
+                    SrChangeKind::Outage { start, end } => {
+                        problems.check(start < end, format!("{}.end", path), "must be after start");
+                    }
+                    SrChangeKind::Periodic { period, .. } => {
+                        problems.check(period > 0, format!("{}.period", path), "must be positive");
+                    }
+                }
+                if let SrChangeKind::Incidents { every, probability, .. } = change.change {
+                    problems.check(every > 0, format!("{}.every", path), "must be positive");
+                    problems.check(
+                        (0.0..=1.0).contains(&probability),
+                        format!("{}.probability", path),
+                        "must be between 0 and 1",
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_merchant(&self, problems: &mut Problems) {
+        for (connector, details) in sorted(&self.merchant.connectors_list) {
+            let path = format!("merchant.connectors_list.{}", connector.0);
+            problems.check(
+                self.psp.psp_variants.contains_key(connector),
+                &path,
+                "has no matching entry in psp.psp_variants",
+            );
+            for (payment_method, config) in sorted(&details.supported_payment_methods) {
+                if let Some(behaviours) = &config.supported_behaviours {
+                    let path = format!("{}.supported_payment_methods.{}.supported_behaviours", path, payment_method.0);
+                    behaviours.validate(&path, problems);
+                }
+            }
+        }
+    }
+}
+
+impl RoutingConfig {
+    /// Check the parameters of the algorithm, under the JSON path `path`.
+    fn validate(&self, path: &str, problems: &mut Problems) {
+        let between = |problems: &mut Problems, field: &str, value: f64| {
+            problems.check((0.0..=1.0).contains(&value), format!("{}.{}", path, field), "must be between 0 and 1");
+        };
+        match self {
+            RoutingConfig::StraightThrough | RoutingConfig::Oracle => {}
+            RoutingConfig::SuccessRate { window, exploration_percentage } => {
+                let window_size = match window {
+                    SlidingWindow::LastTransactions(size) => *size as u64,
+                    SlidingWindow::LastSeconds(seconds) => *seconds,
+                };
+                problems.check(window_size > 0, format!("{}.window", path), "must be positive");
+                problems.check(
+                    *exploration_percentage <= 100,
+                    format!("{}.exploration_percentage", path),
+                    "must be at most 100",
+                );
+            }
+            RoutingConfig::EpsilonGreedy { epsilon, decay, min_epsilon } => {
+                between(problems, "epsilon", *epsilon);
+                between(problems, "decay", *decay);
+                between(problems, "min_epsilon", *min_epsilon);
+            }
+            RoutingConfig::Ucb1 { exploration_factor } => problems.check(
+                *exploration_factor >= 0.0,
+                format!("{}.exploration_factor", path),
+                "must not be negative",
+            ),
+            RoutingConfig::ThompsonSampling { prior_alpha, prior_beta } => {
+                problems.check(*prior_alpha > 0.0, format!("{}.prior_alpha", path), "must be positive");
+                problems.check(*prior_beta > 0.0, format!("{}.prior_beta", path), "must be positive");
+            }
+            RoutingConfig::Dynamo { timeout_ms, fallback, .. } => {
+                problems.check(*timeout_ms > 0, format!("{}.timeout_ms", path), "must be positive");
+                fallback.validate(&format!("{}.fallback", path), problems);
+            }
+        }
+    }
+}
+
+impl Behaviours {
+    fn validate(&self, path: &str, problems: &mut Problems) {
+        for predicate in &self.0 {
+            match predicate {
+                Predicate::AmountBetween { min, max } => problems.check(
+                    min <= max,
+                    format!("{}.amount_between", path),
+                    format!("min ({}) is greater than max ({})", min, max),
+                ),
+                Predicate::All(rules) => validate_rules(rules, &format!("{}.all", path), problems),
+                Predicate::Any(rules) => validate_rules(rules, &format!("{}.any", path), problems),
+                Predicate::Not(rule) => rule.validate(&format!("{}.not", path), problems),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn validate_rules(rules: &[Behaviours], path: &str, problems: &mut Problems) {
+    problems.check(!rules.is_empty(), path, "needs at least one rule");
+    for (index, rule) in rules.iter().enumerate() {
+        rule.validate(&format!("{}[{}]", path, index), problems);
+    }
+}
+
+fn percentage(details: &PaymentMethodDetails) -> u8 {
+    match details {
+        PaymentMethodDetails::Percentage(percentage) | PaymentMethodDetails::Composite { percentage, .. } => *percentage,
+    }
+}
+
+fn check_payment_method_srs(problems: &mut Problems, path: &str, types: &PaymentMethodTypes) {
+    match types {
+        PaymentMethodTypes::PaymentTypes(types) => {
+            for (index, detail) in types.iter().enumerate() {
+                check_sr(problems, format!("{}[{}].sr", path, index), detail.sr as f64);
+            }
+        }
+        PaymentMethodTypes::Simple { sr } => check_sr(problems, format!("{}.sr", path), *sr as f64),
+    }
+}
+
+fn check_sr(problems: &mut Problems, path: String, sr: f64) {
+    problems.check((0.0..=100.0).contains(&sr), path, format!("{} is not a percentage between 0 and 100", sr));
+}
+
+impl SimulationConfig {
+    /// Check the percentages of every dimension, under the JSON path `path`.
+    fn validate(&self, path: &str, problems: &mut Problems) {
+        for (dimension, payment_methods) in sorted(&**self) {
+            payment_methods.validate(&format!("{}.{}", path, dimension.0), problems);
+        }
+    }
+}
+
+impl PaymentMethods {
+    fn validate(&self, path: &str, problems: &mut Problems) {
+        let mut total = 0u32;
+        for (key, value) in sorted(&**self) {
+            match value {
+                PaymentMethodDetails::Percentage(percentage) => total += *percentage as u32,
+                PaymentMethodDetails::Composite { percentage, next, .. } => {
+                    total += *percentage as u32;
+                    next.validate(&format!("{}.{}.next", path, key.0), problems);
+                }
+            }
+        }
+        problems.check(total == 100, path, format!("percentages add up to {}, not 100", total));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// A valid config: cards split between credit and debit, routed to adyen and stripe.
+    fn base() -> Value {
+        json!({
+            "user": {
+                "payment_methods": {
+                    "card": {"percentage": 100, "next": {"payment_method_type": {"credit": 60, "debit": 40}}}
+                }
+            },
+            "psp": {
+                "psp_variants": {
+                    "adyen": {"payment_methods": {"card": [{"payment_method_type": "credit", "sr": 80}]}},
+                    "stripe": {"payment_methods": {"card": {"sr": 70}}}
+                }
+            },
+            "merchant": {
+                "connectors_list": {
+                    "adyen": {"supported_payment_methods": {"card": {}}},
+                    "stripe": {"supported_payment_methods": {"card": {"payment_method_types": ["credit", "debit"]}}}
+                }
+            }
+        })
+    }
+
+    fn problems(config: Value) -> Vec<String> {
+        let config: Config = serde_json::from_value(config).unwrap();
+        config.validate().iter().map(ConfigError::to_string).collect()
+    }
+
+    #[test]
+    fn a_valid_config_has_no_problems() {
+        assert_eq!(problems(base()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_its_path() {
+        let mut config = base();
+        config["user"]["amount"] = json!({"min": 500, "max": 100});
+        config["user"]["payment_methods"]["card"]["next"]["payment_method_type"]["debit"] = json!(30);
+        config["psp"]["psp_variants"]["adyen"]["payment_methods"]["card"][0]["sr"] = json!(120);
+        config["psp"]["otherwise"] = json!("maybe");
+        config["merchant"]["connectors_list"]["checkout"] = json!({"supported_payment_methods": {"card": {}}});
+        config["retry"] = json!({"max_attempts": 0});
+        assert_eq!(
+            problems(config),
+            vec![
+                "user.amount: min (500) is greater than max (100)",
+                "user.payment_methods.card.next.payment_method_type: percentages add up to 90, not 100",
+                "psp.otherwise: must be \"success\" or \"failure\", not \"maybe\"",
+                "psp.psp_variants.adyen.payment_methods.card[0].sr: 120 is not a percentage between 0 and 100",
+                "merchant.connectors_list.checkout: has no matching entry in psp.psp_variants",
+                "retry.max_attempts: must be at least 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn behaviours_arrivals_and_routing_are_checked() {
+        let mut config = base();
+        config["merchant"]["connectors_list"]["adyen"]["supported_payment_methods"]["card"]["supported_behaviours"] =
+            json!({"any": [{"amount_between": {"min": 10, "max": 5}}], "not": {"all": []}});
+        config["arrivals"] = json!({"process": "poisson", "tps": 0});
+        config["routing"] = json!({"algorithm": "dynamo", "decision_url": "http://localhost", "fallback": {
+            "algorithm": "epsilon_greedy", "epsilon": 1.5
+        }});
+        let behaviours = "merchant.connectors_list.adyen.supported_payment_methods.card.supported_behaviours";
+        assert_eq!(
+            problems(config),
+            vec![
+                format!("{}.any[0].amount_between: min (10) is greater than max (5)", behaviours),
+                format!("{}.not.all: needs at least one rule", behaviours),
+                "arrivals.tps: must be positive".to_string(),
+                "routing.fallback.epsilon: must be between 0 and 1".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_payment_method_types_and_currencies_are_reported() {
+        let mut config = base();
+        config["user"]["payment_methods"]["card"]["next"]["payment_method_type"] = json!({"credit": 60, "prepaid": 40});
+        config["merchant"]["connectors_list"]["adyen"]["supported_payment_methods"]["card"]["payment_method_types"] =
+            json!(["credit"]);
+        config["user"]["currency"] = json!("JPY");
+        for connector in ["adyen", "stripe"] {
+            config["merchant"]["connectors_list"][connector]["supported_currencies"] = json!(["USD"]);
+        }
+        assert_eq!(
+            problems(config),
+            vec![
+                "user.currency: not supported by any connector in merchant.connectors_list",
+                "user.payment_methods.card.next.payment_method_type.prepaid: not supported by any connector in merchant.connectors_list",
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use anyhow::Result;
+use serde_json::json;
+use testing_framework::config::{Feedback, Key, Sample, Status};
+use testing_framework::mock_server::{MockDecision, MockDynamoServer};
+use testing_framework::router::{DynamoRouting, Router};
+
+/// Fallback that always answers with the last eligible connector, so tests can
+/// tell it apart from the mock server's `MockDecision::First`.
+struct LastConnector;
+
+impl Router for LastConnector {
+    fn name(&self) -> &str {
+        "last_connector"
+    }
+
+    fn get_connector(&mut self, _sample: &Sample, connectors: &[Key]) -> Result<Key> {
+        Ok(connectors[connectors.len() - 1].clone())
+    }
+}
+
+fn key(value: &str) -> Key {
+    Key(value.to_string())
+}
+
+fn sample() -> Sample {
+    Sample {
+        amount: 1200,
+        currency: "USD".to_string(),
+        payment_method: key("card"),
+        payment_method_type: Some(key("credit")),
+        attributes: HashMap::from([(key("auth_type"), json!("3ds"))]),
+        timestamp_ms: 0,
+    }
+}
+
+fn connectors() -> Vec<Key> {
+    vec![key("stripe"), key("adyen"), key("paypal")]
+}
+
+fn router(server: &MockDynamoServer, timeout: Duration) -> DynamoRouting {
+    DynamoRouting::new(
+        server.decision_url(),
+        Some(server.feedback_url()),
+        timeout,
+        Box::new(LastConnector),
+    )
+    .unwrap()
+}
+
+#[test]
+fn uses_connector_chosen_by_service() {
+    let server = MockDynamoServer::start(MockDecision::Prefer(key("adyen")), Duration::ZERO).unwrap();
+    let mut router = router(&server, Duration::from_secs(5));
+
+    let connector = router.get_connector(&sample(), &connectors()).unwrap();
+
+    assert_eq!(connector, key("adyen"));
+}
+
+#[test]
+fn posts_outcome_as_feedback() {
+    let server = MockDynamoServer::start(MockDecision::First, Duration::ZERO).unwrap();
+    let mut router = router(&server, Duration::from_secs(5));
+
+    let connector = router.get_connector(&sample(), &connectors()).unwrap();
+    router
+        .feedback(&Feedback {
+            connector: connector.clone(),
+            sample: sample(),
+            status: Status::Failure,
+            latency: Duration::from_millis(420),
+            timestamp: Duration::from_secs(3),
+        })
+        .unwrap();
+
+    let received = server.received_feedback();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].connector, key("stripe"));
+    assert_eq!(received[0].status, Status::Failure);
+    assert_eq!(received[0].latency_ms, 420);
+    assert_eq!(received[0].sample, sample());
+}
+
+#[test]
+fn falls_back_when_service_errors() {
+    let server = MockDynamoServer::start(MockDecision::Error, Duration::ZERO).unwrap();
+    let mut router = router(&server, Duration::from_secs(5));
+
+    let connector = router.get_connector(&sample(), &connectors()).unwrap();
+
+    assert_eq!(connector, key("paypal"));
+}
+
+#[test]
+fn falls_back_when_service_times_out() {
+    let server = MockDynamoServer::start(MockDecision::First, Duration::from_millis(500)).unwrap();
+    let mut router = router(&server, Duration::from_millis(50));
+
+    let connector = router.get_connector(&sample(), &connectors()).unwrap();
+
+    assert_eq!(connector, key("paypal"));
+}
+
+#[test]
+fn falls_back_when_service_picks_ineligible_connector() {
+    let server = MockDynamoServer::start(MockDecision::Prefer(key("worldpay")), Duration::ZERO).unwrap();
+    let mut router = router(&server, Duration::from_secs(5));
+
+    let connector = router.get_connector(&sample(), &connectors()).unwrap();
+
+    assert_eq!(connector, key("paypal"));
+}
+
+#[test]
+fn falls_back_when_service_is_unreachable() {
+    let server = MockDynamoServer::start(MockDecision::First, Duration::ZERO).unwrap();
+    let decision_url = server.decision_url();
+    drop(server);
+    let mut router = DynamoRouting::new(
+        decision_url,
+        None,
+        Duration::from_secs(1),
+        Box::new(LastConnector),
+    )
+    .unwrap();
+
+    let connector = router.get_connector(&sample(), &connectors()).unwrap();
+
+    assert_eq!(connector, key("paypal"));
+}